@@ -0,0 +1,29 @@
+use crate::backend::filesystem_backend::FileSystemBackend;
+use crate::backend::mock_backend::MockBackend;
+use crate::backend::system_backend::SystemBackend;
+use crate::backend::terminal_backend::{TerminalBackend, TerminalSessions};
+use crate::backend::web_backend::WebBackend;
+
+/// The backends a scenario runs its actions on and checks its conditions against.
+pub struct Backends {
+    pub terminal: TerminalBackend,
+    pub sessions: TerminalSessions,
+    pub fs: FileSystemBackend,
+    pub web: WebBackend,
+    pub mock: MockBackend,
+    pub system: SystemBackend,
+}
+
+impl Backends {
+    /// Runs `f` with `terminal` standing in for the main terminal, e.g. a named session's.
+    pub fn with_terminal<R>(
+        &mut self,
+        terminal: &mut TerminalBackend,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        std::mem::swap(&mut self.terminal, terminal);
+        let result = f(self);
+        std::mem::swap(&mut self.terminal, terminal);
+        result
+    }
+}
@@ -1,13 +1,31 @@
+use crate::backend::snapshot::SnapshotStore;
 use crate::parser::ast::Action;
 use std::collections::HashMap;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
-pub struct FileSystemBackend {}
+pub struct FileSystemBackend {
+    pub snapshots: SnapshotStore,
+    /// The directory of the test suite, which `copy_dir` copies fixtures from.
+    base_dir: PathBuf,
+}
 
 impl FileSystemBackend {
     pub fn new() -> Self {
-        Self {}
+        Self::with_snapshots(SnapshotStore::default())
+    }
+
+    pub fn with_snapshots(snapshots: SnapshotStore) -> Self {
+        Self {
+            snapshots,
+            base_dir: PathBuf::from("."),
+        }
+    }
+
+    pub fn with_base_dir(mut self, base_dir: PathBuf) -> Self {
+        self.base_dir = base_dir;
+        self
     }
 
     pub(crate) fn resolve_path(&self, path: &str, cwd: &Path) -> PathBuf {
@@ -64,6 +82,22 @@ impl FileSystemBackend {
                 }
                 true
             }
+            Action::CopyDir {
+                source,
+                destination,
+            } => {
+                let from = self.resolve_path(source, &self.base_dir);
+                let to = self.resolve_path(destination, cwd);
+                copy_dir(&from, &to).unwrap_or_else(|e| {
+                    panic!(
+                        "Failed to copy directory {} to {}: {}",
+                        from.display(),
+                        to.display(),
+                        e
+                    )
+                });
+                true
+            }
             Action::ReadFile { path, variable } => {
                 let resolved_path = self.resolve_path(path, cwd);
                 match fs::read_to_string(&resolved_path) {
@@ -139,3 +173,19 @@ impl FileSystemBackend {
         !resolved_path.exists()
     }
 }
+
+/// Recursively copies the contents of `from` into `to`, creating `to` if needed and
+/// overwriting files that already exist.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
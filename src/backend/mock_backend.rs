@@ -0,0 +1,399 @@
+use crate::colours;
+use crate::parser::ast::{Action, Condition, MockResponse, MockRoute};
+use crate::parser::helpers::substitute_string;
+use serde_json::{Map, Value as JsonValue, json};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// A request received by a mock server.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    received_at: Instant,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the request matches a route's method and path. The path may contain `*`
+    /// wildcards; it is only compared with the query string if it contains a `?`.
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        let target = if path.contains('?') && !self.query.is_empty() {
+            format!("{}?{}", self.path, self.query)
+        } else {
+            self.path.clone()
+        };
+        (method == "ANY" || method.eq_ignore_ascii_case(&self.method))
+            && glob::Pattern::new(path).is_ok_and(|pattern| pattern.matches(&target))
+    }
+
+    /// The request as the `request` variable of a response template, e.g. `${request.path}`
+    /// or `${request.json.name}`.
+    fn template_vars(&self) -> HashMap<String, String> {
+        let query: Map<String, JsonValue> = self
+            .query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (key.to_string(), JsonValue::String(value.to_string()))
+            })
+            .collect();
+        let headers: Map<String, JsonValue> = self
+            .headers
+            .iter()
+            .map(|(key, value)| (key.to_lowercase(), JsonValue::String(value.clone())))
+            .collect();
+        let request = json!({
+            "method": self.method,
+            "path": self.path,
+            "query": query,
+            "headers": headers,
+            "body": self.body,
+            "json": serde_json::from_str::<JsonValue>(&self.body).unwrap_or(JsonValue::Null),
+        });
+        HashMap::from([("request".to_string(), request.to_string())])
+    }
+}
+
+/// A route and how many more times it may answer (`None` for no limit).
+type Routes = Vec<(MockRoute, Option<usize>)>;
+
+/// A stub HTTP server on a local port, serving the routes of a `Mock serve` block.
+#[derive(Debug)]
+struct MockServer {
+    routes: Arc<Mutex<Routes>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    fn start(port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+
+        let routes = Arc::new(Mutex::new(Vec::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (routes, requests, stop) = (routes.clone(), requests.clone(), stop.clone());
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            let (routes, requests) = (routes.clone(), requests.clone());
+                            thread::spawn(move || handle_connection(stream, &routes, &requests));
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                            thread::sleep(Duration::from_millis(10))
+                        }
+                        Err(_) => thread::sleep(Duration::from_millis(10)),
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            routes,
+            requests,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The stub servers started with `Mock serve`. They run until the backend is dropped at the
+/// end of the scenario, or, for servers started in `background`, at the end of the suite.
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    servers: HashMap<u16, Arc<MockServer>>,
+    /// Ports of the background's servers, which a scenario uses but cannot serve again.
+    inherited: HashSet<u16>,
+    /// When the scenario started. Requests received before then are not the scenario's.
+    since: Option<Instant>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A backend for a scenario that shares the servers started so far, e.g. in the
+    /// background, and only sees the requests they receive from now on.
+    pub fn for_scenario(&self) -> Self {
+        Self {
+            servers: self.servers.clone(),
+            inherited: self.servers.keys().copied().collect(),
+            since: Some(Instant::now()),
+        }
+    }
+
+    /// Executes a mock action. Returns true if the action was handled.
+    pub fn execute_action(&mut self, action: &Action, verbose: bool) -> bool {
+        let Action::MockServe { port, routes } = action else {
+            return false;
+        };
+
+        if self.inherited.contains(port) {
+            colours::error(&format!(
+                "[MOCK] Port {} is served by the background for the whole suite",
+                port
+            ));
+            return true;
+        }
+        // Serving on a port again replaces its routes and forgets its requests.
+        if !self.servers.contains_key(port) {
+            match MockServer::start(*port) {
+                Ok(server) => {
+                    self.servers.insert(*port, Arc::new(server));
+                }
+                Err(e) => {
+                    colours::error(&format!(
+                        "[MOCK] Failed to start mock server on port {}: {}",
+                        port, e
+                    ));
+                    return true;
+                }
+            }
+        }
+        let server = &self.servers[port];
+        *server.routes.lock().unwrap() = routes
+            .iter()
+            .map(|route| (route.clone(), route.times))
+            .collect();
+        server.requests.lock().unwrap().clear();
+
+        if verbose {
+            colours::info(&format!(
+                "[MOCK] Serving {} route(s) on http://127.0.0.1:{}",
+                routes.len(),
+                port
+            ));
+        }
+        true
+    }
+
+    /// All requests received by the mock servers since the scenario started, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        let mut requests: Vec<RecordedRequest> = self
+            .servers
+            .values()
+            .flat_map(|server| server.requests.lock().unwrap().clone())
+            .filter(|request| self.since.is_none_or(|since| request.received_at >= since))
+            .collect();
+        requests.sort_by_key(|request| request.received_at);
+        requests
+    }
+
+    pub fn last_request(&self) -> Option<RecordedRequest> {
+        self.requests().pop()
+    }
+
+    /// Checks a single mock condition against the requests received so far.
+    pub fn check_condition(&self, condition: &Condition, verbose: bool) -> bool {
+        match condition {
+            Condition::MockReceived {
+                method,
+                path,
+                times,
+            } => {
+                let count = self
+                    .requests()
+                    .iter()
+                    .filter(|request| request.matches(method, path))
+                    .count();
+                if verbose {
+                    println!("[MOCK] Received {} {} {} time(s)", method, path, count);
+                }
+                match times {
+                    Some(times) => count == *times,
+                    None => count > 0,
+                }
+            }
+            Condition::MockLastRequestBodyContains { text } => self
+                .last_request()
+                .is_some_and(|request| request.body.contains(text)),
+            Condition::MockLastRequestHeaderEquals { name, value } => self
+                .last_request()
+                .is_some_and(|request| request.header(name) == Some(value.as_str())),
+            Condition::MockLastRequestJsonPathEquals {
+                path,
+                expected_value,
+            } => self.last_request().is_some_and(|request| {
+                serde_json::from_str::<JsonValue>(&request.body)
+                    .ok()
+                    .and_then(|body| body.pointer(path).cloned())
+                    .is_some_and(|actual| expected_value.matches_json(&actual))
+            }),
+            _ => false,
+        }
+    }
+}
+
+/// Reads one request from the connection, records it and answers it with the first route
+/// that matches. Requests that match no route get a 404.
+fn handle_connection(
+    stream: TcpStream,
+    routes: &Mutex<Routes>,
+    requests: &Mutex<Vec<RecordedRequest>>,
+) {
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let Some(request) = read_request(&stream) else {
+        return;
+    };
+    requests.lock().unwrap().push(request.clone());
+
+    let route = {
+        let mut routes = routes.lock().unwrap();
+        routes
+            .iter_mut()
+            .find(|(route, remaining)| {
+                *remaining != Some(0) && request.matches(&route.method, &route.path)
+            })
+            .map(|(route, remaining)| {
+                if let Some(remaining) = remaining {
+                    *remaining -= 1;
+                }
+                route.clone()
+            })
+    };
+
+    let Some(route) = route else {
+        let body = format!("No mock route for {} {}", request.method, request.path);
+        write_response(&stream, 404, &[], &body);
+        return;
+    };
+    if route.delay > 0.0 {
+        thread::sleep(Duration::from_secs_f32(route.delay));
+    }
+    match &route.response {
+        MockResponse::Respond {
+            status,
+            body,
+            headers,
+        } => {
+            let vars = request.template_vars();
+            let headers: Vec<(String, String)> = headers
+                .iter()
+                .map(|(key, value)| (key.clone(), substitute_string(value, &vars)))
+                .collect();
+            write_response(&stream, *status, &headers, &substitute_string(body, &vars));
+        }
+        MockResponse::CloseConnection => {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Option<RecordedRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(RecordedRequest {
+        method,
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+        received_at: Instant::now(),
+    })
+}
+
+fn write_response(mut stream: &TcpStream, status: u16, headers: &[(String, String)], body: &str) {
+    let mut response = format!("HTTP/1.1 {} {}\r\n", status, reason_phrase(status));
+    if !headers
+        .iter()
+        .any(|(key, _)| key.eq_ignore_ascii_case("content-type"))
+    {
+        let trimmed = body.trim_start();
+        let content_type = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            "application/json"
+        } else {
+            "text/plain; charset=utf-8"
+        };
+        response.push_str(&format!("Content-Type: {}\r\n", content_type));
+    }
+    for (key, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    response.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
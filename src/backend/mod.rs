@@ -1,5 +1,10 @@
+pub mod backends;
 pub mod filesystem_backend;
+pub mod mock_backend;
+pub mod process;
 pub mod report;
+pub mod snapshot;
 pub mod system_backend;
 pub mod terminal_backend;
 pub mod web_backend;
+pub mod workspace;
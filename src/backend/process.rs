@@ -0,0 +1,417 @@
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Child, ChildStderr, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a process group is given to exit after SIGTERM before it is killed.
+pub const STOP_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// Process groups started by choreo that are still running, so they can be killed on Ctrl-C.
+fn running_groups() -> &'static Mutex<Vec<u32>> {
+    static GROUPS: OnceLock<Mutex<Vec<u32>>> = OnceLock::new();
+    GROUPS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Spawns `cmd` as the leader of a new process group, so everything it starts can be
+/// stopped together with `stop_group`. Call `release_group` once it has been waited for.
+pub fn spawn_in_group(cmd: &mut Command) -> io::Result<Child> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    let child = cmd.spawn()?;
+    register_group(child.id());
+    Ok(child)
+}
+
+/// Remembers a process group started by choreo, so it is killed on Ctrl-C.
+/// Call `release_group` once it has been waited for.
+pub fn register_group(pgid: u32) {
+    if let Ok(mut groups) = running_groups().lock() {
+        groups.push(pgid);
+    }
+}
+
+/// Forgets a process group once its leader has exited.
+pub fn release_group(pgid: u32) {
+    if let Ok(mut groups) = running_groups().lock() {
+        groups.retain(|&running| running != pgid);
+    }
+}
+
+/// Stops the process group led by `child`: SIGTERM first, then SIGKILL if the leader is
+/// still running after `grace`. Returns the PIDs that were in the group, and the leader's status.
+pub fn stop_group(child: &mut Child, grace: Duration) -> (Vec<u32>, Option<ExitStatus>) {
+    let pgid = child.id();
+    let members = group_members(pgid);
+    signal_group(pgid, Signal::Terminate);
+    let start = Instant::now();
+    let mut status = None;
+    while start.elapsed() < grace {
+        if let Ok(Some(s)) = child.try_wait() {
+            status = Some(s);
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    // Also catches anything in the group that outlived the leader.
+    signal_group(pgid, Signal::Kill);
+    if status.is_none() {
+        #[cfg(not(unix))]
+        let _ = child.kill();
+        status = child.wait().ok();
+    }
+    release_group(pgid);
+    (members, status)
+}
+
+/// Kills every process group that is still running. Used when choreo is interrupted.
+pub fn kill_all_running() {
+    let groups = running_groups()
+        .lock()
+        .map(|g| g.clone())
+        .unwrap_or_default();
+    for pgid in groups {
+        signal_group(pgid, Signal::Kill);
+    }
+}
+
+/// Installs a Ctrl-C handler that kills all managed processes before exiting.
+pub fn install_interrupt_handler() {
+    let _ = ctrlc::set_handler(|| {
+        kill_all_running();
+        std::process::exit(130);
+    });
+}
+
+/// The signals that can be named in a test, e.g. `send_signal "SIGINT"`.
+#[cfg(unix)]
+const SIGNALS: [(&str, i32); 15] = [
+    ("SIGHUP", libc::SIGHUP),
+    ("SIGINT", libc::SIGINT),
+    ("SIGQUIT", libc::SIGQUIT),
+    ("SIGILL", libc::SIGILL),
+    ("SIGABRT", libc::SIGABRT),
+    ("SIGKILL", libc::SIGKILL),
+    ("SIGUSR1", libc::SIGUSR1),
+    ("SIGSEGV", libc::SIGSEGV),
+    ("SIGUSR2", libc::SIGUSR2),
+    ("SIGPIPE", libc::SIGPIPE),
+    ("SIGALRM", libc::SIGALRM),
+    ("SIGTERM", libc::SIGTERM),
+    ("SIGCONT", libc::SIGCONT),
+    ("SIGSTOP", libc::SIGSTOP),
+    ("SIGTSTP", libc::SIGTSTP),
+];
+
+#[cfg(not(unix))]
+const SIGNALS: [(&str, i32); 0] = [];
+
+/// The number of a signal given by name, with or without the `SIG` prefix (`SIGTERM`, `term`).
+pub fn signal_number(name: &str) -> Option<i32> {
+    let name = name.trim().to_ascii_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{}", name)
+    };
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|&(_, number)| number)
+}
+
+/// The name of a signal, e.g. `SIGTERM` for 15.
+pub fn signal_name(number: i32) -> String {
+    SIGNALS
+        .iter()
+        .find(|&&(_, known)| known == number)
+        .map_or_else(
+            || format!("signal {}", number),
+            |(name, _)| name.to_string(),
+        )
+}
+
+/// The signal that ended a process, if it was killed by one.
+pub fn terminating_signal(status: ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+/// The exit code of a process. Like a shell, a process ended by a signal reports 128 plus
+/// the signal number (130 for SIGINT, 143 for SIGTERM, 137 for SIGKILL).
+pub fn exit_code_of(status: ExitStatus) -> Option<i32> {
+    terminating_signal(status).map_or(status.code(), |signal| Some(128 + signal))
+}
+
+/// Output captured from a process.
+#[derive(Default)]
+struct CapturedOutput {
+    /// Stdout and stderr in the order they arrived.
+    combined: Vec<u8>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+/// Reads the stdout and stderr of a command while it runs, keeping the order in which
+/// the two were written.
+pub struct StreamReader {
+    output: Arc<Mutex<CapturedOutput>>,
+    threads: [JoinHandle<()>; 2],
+}
+
+impl StreamReader {
+    pub fn start(stdout: ChildStdout, stderr: ChildStderr) -> Self {
+        let output = Arc::new(Mutex::new(CapturedOutput::default()));
+        let threads = [
+            capture(stdout, Arc::clone(&output), false),
+            capture(stderr, Arc::clone(&output), true),
+        ];
+        Self { output, threads }
+    }
+
+    /// Returns true once both streams are closed, i.e. `finish` will not block.
+    pub fn is_finished(&self) -> bool {
+        self.threads.iter().all(|thread| thread.is_finished())
+    }
+
+    /// Waits until both streams are closed. Returns stdout, stderr and both interleaved.
+    pub fn finish(self) -> (String, String, String) {
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+        Self::collect(&self.output)
+    }
+
+    /// Like `finish`, but gives up waiting after `grace`, e.g. when a process outside the
+    /// command's group still holds the streams open. Returns what was read until then.
+    pub fn finish_within(self, grace: Duration) -> (String, String, String) {
+        let start = Instant::now();
+        while !self.is_finished() && start.elapsed() < grace {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if self.is_finished() {
+            return self.finish();
+        }
+        Self::collect(&self.output)
+    }
+
+    fn collect(output: &Mutex<CapturedOutput>) -> (String, String, String) {
+        let output = output.lock().unwrap();
+        (
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+            String::from_utf8_lossy(&output.combined).into_owned(),
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Signal {
+    Terminate,
+    Kill,
+    Number(i32),
+}
+
+/// A background process started with `Terminal start "..." as name`. It runs in its
+/// own process group, its output is captured as it is printed, and it is stopped when dropped.
+pub struct ManagedProcess {
+    child: Child,
+    output: Arc<Mutex<CapturedOutput>>,
+    status: Option<ExitStatus>,
+    /// Set once the process group is gone or has been stopped, after which its id may be
+    /// reused and must not be signalled.
+    released: bool,
+}
+
+impl ManagedProcess {
+    /// Starts `command` with `shell -c` in `cwd`, with `env` added to its environment.
+    pub fn start(
+        shell: &str,
+        command: &str,
+        cwd: &Path,
+        env: &[(String, String)],
+    ) -> io::Result<Self> {
+        let mut child = spawn_in_group(
+            Command::new(shell)
+                .arg("-c")
+                .arg(command)
+                .current_dir(cwd)
+                .envs(env.iter().cloned())
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )?;
+
+        let output = Arc::new(Mutex::new(CapturedOutput::default()));
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        capture(stdout, Arc::clone(&output), false);
+        capture(stderr, Arc::clone(&output), true);
+
+        Ok(Self {
+            child,
+            output,
+            status: None,
+            released: false,
+        })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Returns true while the process has not exited.
+    pub fn is_running(&mut self) -> bool {
+        self.poll().is_none()
+    }
+
+    /// The exit code, once the process has exited. See `exit_code_of`.
+    pub fn exit_code(&mut self) -> Option<i32> {
+        self.poll().and_then(exit_code_of)
+    }
+
+    /// The signal that ended the process, once it has exited.
+    pub fn signal(&mut self) -> Option<i32> {
+        self.poll().and_then(terminating_signal)
+    }
+
+    /// Sends a signal to the process and everything it started.
+    pub fn send_signal(&mut self, signal: i32) {
+        if self.is_running() {
+            signal_group(self.pid(), Signal::Number(signal));
+        }
+    }
+
+    /// Stdout and stderr as they were printed.
+    pub fn output(&self) -> String {
+        self.read(|o| &o.combined)
+    }
+
+    pub fn stdout(&self) -> String {
+        self.read(|o| &o.stdout)
+    }
+
+    pub fn stderr(&self) -> String {
+        self.read(|o| &o.stderr)
+    }
+
+    fn read(&self, stream: fn(&CapturedOutput) -> &Vec<u8>) -> String {
+        self.output
+            .lock()
+            .map(|o| String::from_utf8_lossy(stream(&o)).into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Stops the process and everything it started. Returns the PIDs that were stopped.
+    pub fn stop(&mut self, grace: Duration) -> Vec<u32> {
+        if self.is_running() {
+            let (members, status) = stop_group(&mut self.child, grace);
+            self.status = status;
+            self.released = true;
+            return members;
+        }
+        if self.released {
+            return Vec::new();
+        }
+        // The command has exited, but left processes running in its group.
+        let members = group_members(self.pid());
+        signal_group(self.pid(), Signal::Kill);
+        self.release();
+        members
+    }
+
+    fn poll(&mut self) -> Option<ExitStatus> {
+        if self.status.is_none()
+            && let Ok(Some(status)) = self.child.try_wait()
+        {
+            self.status = Some(status);
+            // A command that daemonizes exits while what it started keeps running in its group.
+            if group_members(self.pid()).is_empty() {
+                self.release();
+            }
+        }
+        self.status
+    }
+
+    fn release(&mut self) {
+        release_group(self.pid());
+        self.released = true;
+    }
+}
+
+impl Drop for ManagedProcess {
+    fn drop(&mut self) {
+        self.stop(STOP_GRACE_PERIOD);
+    }
+}
+
+/// Copies everything the process prints on one stream into the captured output.
+fn capture<R: Read + Send + 'static>(
+    mut stream: R,
+    output: Arc<Mutex<CapturedOutput>>,
+    is_stderr: bool,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut chunk = [0u8; 4096];
+        while let Ok(n @ 1..) = stream.read(&mut chunk) {
+            if let Ok(mut o) = output.lock() {
+                o.combined.extend_from_slice(&chunk[..n]);
+                if is_stderr {
+                    o.stderr.extend_from_slice(&chunk[..n]);
+                } else {
+                    o.stdout.extend_from_slice(&chunk[..n]);
+                }
+            }
+        }
+    })
+}
+
+#[cfg(unix)]
+fn signal_group(pgid: u32, signal: Signal) {
+    let signal = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+        Signal::Number(number) => number,
+    };
+    // A negative pid addresses the whole process group.
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn signal_group(_pgid: u32, _signal: Signal) {}
+
+/// The PIDs of the processes in a process group.
+#[cfg(unix)]
+fn group_members(pgid: u32) -> Vec<u32> {
+    let mut system = sysinfo::System::new();
+    system.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
+    let mut members: Vec<u32> = system
+        .processes()
+        .iter()
+        // Threads are listed too; only whole processes are of interest.
+        .filter(|(_, process)| process.thread_kind().is_none())
+        .map(|(pid, _)| pid.as_u32())
+        .filter(|&pid| unsafe { libc::getpgid(pid as libc::pid_t) } == pgid as libc::pid_t)
+        .collect();
+    members.sort();
+    members
+}
+
+#[cfg(not(unix))]
+fn group_members(pgid: u32) -> Vec<u32> {
+    vec![pgid]
+}
@@ -16,24 +16,98 @@ pub enum TestStatus {
     Failed,
     Skipped,
     Running,
+    Errored,
+}
+
+/// Stdout/stderr captured while a test's actions were executed.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CapturedOutput {
+    /// Adds output that was produced after this output.
+    pub fn append(&mut self, other: CapturedOutput) {
+        self.stdout.push_str(&other.stdout);
+        self.stderr.push_str(&other.stderr);
+    }
+}
+
+/// The root `<testsuites>` element of a JUnit XML report.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename = "testsuites")]
+pub struct JunitReport {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "@tests")]
+    pub tests: usize,
+    #[serde(rename = "@failures")]
+    pub failures: usize,
+    #[serde(rename = "@errors")]
+    pub errors: usize,
+    #[serde(rename = "@skipped")]
+    pub skipped: usize,
+    #[serde(rename = "@time", serialize_with = "to_seconds_str")]
+    pub time: Duration,
+    #[serde(rename = "testsuite")]
+    pub testsuites: Vec<TestSuiteReport>,
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct TestCaseReport {
+    #[serde(rename = "@name")]
     pub name: String,
-    #[serde(serialize_with = "to_seconds_str")]
+    #[serde(rename = "@classname")]
+    pub classname: String,
+    #[serde(rename = "@file")]
+    pub file: String,
+    #[serde(rename = "@time", serialize_with = "to_seconds_str")]
     pub time: Duration,
+    #[serde(skip)]
     pub status: TestStatus,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub failure_message: Option<String>,
+    pub failure: Option<JunitFailure>,
+    /// Set when the test could not be run at all, e.g. because its suite failed to parse.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JunitFailure>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<JunitSkipped>,
+    #[serde(rename = "system-out", skip_serializing_if = "Option::is_none")]
+    pub system_out: Option<String>,
+    #[serde(rename = "system-err", skip_serializing_if = "Option::is_none")]
+    pub system_err: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct JunitFailure {
+    #[serde(rename = "@message")]
+    pub message: String,
+    #[serde(rename = "@type")]
+    pub kind: String,
+    #[serde(rename = "$text")]
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct JunitSkipped {
+    #[serde(rename = "@message", skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct TestSuiteReport {
+    #[serde(rename = "@name")]
     pub name: String,
+    #[serde(rename = "@tests")]
     pub tests: usize,
+    #[serde(rename = "@failures")]
     pub failures: usize,
-    #[serde(serialize_with = "to_seconds_str")]
+    #[serde(rename = "@errors")]
+    pub errors: usize,
+    #[serde(rename = "@skipped")]
+    pub skipped: usize,
+    #[serde(rename = "@time", serialize_with = "to_seconds_str")]
     pub time: Duration,
     #[serde(rename = "testcase")]
     pub testcases: Vec<TestCaseReport>,
@@ -50,6 +124,9 @@ pub struct Feature {
     pub name: String,
     pub elements: Vec<Scenario>,
     pub summary: Summary,
+    /// Why the suite could not be run, if it failed to load.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -0,0 +1,162 @@
+use crate::colours;
+use regex::Regex;
+use similar::TextDiff;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The redaction rules used when a suite does not set `snapshot_redactions`.
+pub const DEFAULT_REDACTIONS: [&str; 3] = ["timestamps", "uuids", "temp_paths"];
+
+/// Golden files for the `..._matches_snapshot "name"` conditions. Snapshots are stored as
+/// `snapshots/<name>.snap` next to the test suite, with the redaction rules applied first.
+pub struct SnapshotStore {
+    dir: PathBuf,
+    update: bool,
+    redactions: Vec<(Regex, &'static str)>,
+    /// Snapshots to record or rewrite once the test that checked them has passed, by name.
+    pending: Mutex<HashMap<String, String>>,
+}
+
+impl Clone for SnapshotStore {
+    /// The clone starts without pending snapshots: they belong to the tests of the original.
+    fn clone(&self) -> Self {
+        Self {
+            dir: self.dir.clone(),
+            update: self.update,
+            redactions: self.redactions.clone(),
+            pending: Mutex::default(),
+        }
+    }
+}
+
+impl Default for SnapshotStore {
+    fn default() -> Self {
+        Self::new(Path::new("."), false, &[])
+    }
+}
+
+impl SnapshotStore {
+    /// Creates a store for the suite in `base_dir`. With `update`, snapshots that differ are
+    /// rewritten instead of failing. `rules` are the names of built-in rules or regexes.
+    pub fn new(base_dir: &Path, update: bool, rules: &[String]) -> Self {
+        Self {
+            dir: base_dir.join("snapshots"),
+            update,
+            redactions: rules.iter().filter_map(|rule| redaction(rule)).collect(),
+            pending: Mutex::default(),
+        }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.snap", name))
+    }
+
+    /// Replaces everything that matches a redaction rule with a placeholder.
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.replace("\r\n", "\n");
+        for (pattern, placeholder) in &self.redactions {
+            text = pattern.replace_all(&text, *placeholder).into_owned();
+        }
+        format!("{}\n", text.trim_end())
+    }
+
+    /// Compares `actual` with the snapshot called `name` and returns a unified diff if they
+    /// differ. A missing snapshot, or in update mode a changed one, passes and is kept until
+    /// `settle` writes it; the file is not touched here.
+    pub fn check(&self, name: &str, actual: &str) -> Result<(), String> {
+        let actual = self.redact(actual);
+        let expected = fs::read_to_string(self.path(name)).ok();
+        let mut pending = self.pending.lock().unwrap();
+        match &expected {
+            Some(expected) if expected.trim_end() == actual.trim_end() => {
+                pending.remove(name);
+                Ok(())
+            }
+            Some(expected) if !self.update => Err(self.diff(name, expected, &actual)),
+            _ => {
+                pending.insert(name.to_string(), actual);
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the snapshots among `names` that `check` kept, using the output of the last
+    /// check. Called once the test that checked them is done; if it failed, they are dropped.
+    pub fn settle(&self, names: &[String], passed: bool) {
+        let mut pending = self.pending.lock().unwrap();
+        for name in names {
+            let Some(content) = pending.remove(name) else {
+                continue;
+            };
+            if !passed {
+                continue;
+            }
+            let path = self.path(name);
+            let action = if path.exists() { "Updated" } else { "Recorded" };
+            match self.write(&path, &content) {
+                Ok(()) => println!("  [SNAPSHOT] {} {}", action, path.display()),
+                Err(e) => colours::error(&format!("  [SNAPSHOT] {}", e)),
+            }
+        }
+    }
+
+    /// Describes why `actual` does not match the snapshot, without recording anything.
+    pub fn describe_mismatch(&self, name: &str, actual: &str) -> Option<String> {
+        let expected = fs::read_to_string(self.path(name)).ok()?;
+        let actual = self.redact(actual);
+        (expected.trim_end() != actual.trim_end()).then(|| self.diff(name, &expected, &actual))
+    }
+
+    fn diff(&self, name: &str, expected: &str, actual: &str) -> String {
+        let diff = TextDiff::from_lines(expected, actual);
+        format!(
+            "snapshot '{}' does not match:\n{}",
+            name,
+            diff.unified_diff()
+                .context_radius(3)
+                .header(&self.path(name).display().to_string(), "actual")
+        )
+    }
+
+    fn write(&self, path: &Path, content: &str) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, content).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+}
+
+/// Returns true if `rule` is a built-in rule name or a valid regex.
+pub fn is_valid_redaction(rule: &str) -> bool {
+    redaction(rule).is_some()
+}
+
+/// The pattern and placeholder for a redaction rule. Anything that is not the name of a
+/// built-in rule is treated as a regex.
+fn redaction(rule: &str) -> Option<(Regex, &'static str)> {
+    let (pattern, placeholder) = match rule {
+        "timestamps" => (
+            r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:?\d{2})?".to_string(),
+            "[TIMESTAMP]",
+        ),
+        "uuids" => (
+            r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b".to_string(),
+            "[UUID]",
+        ),
+        "temp_paths" => {
+            let temp_dir = std::env::temp_dir();
+            let temp_dir = temp_dir.to_string_lossy();
+            (
+                format!(
+                    r"(?:{}|/tmp)/[^\s'\x22:]*",
+                    regex::escape(temp_dir.trim_end_matches('/'))
+                ),
+                "[TEMP_PATH]",
+            )
+        }
+        custom => (custom.to_string(), "[REDACTED]"),
+    };
+    Regex::new(&pattern).ok().map(|regex| (regex, placeholder))
+}
@@ -1,43 +1,43 @@
+use crate::backend::process::{
+    ManagedProcess, STOP_GRACE_PERIOD, StreamReader, exit_code_of, register_group, release_group,
+    signal_name, signal_number, spawn_in_group, stop_group, terminating_signal,
+};
+use crate::backend::report::CapturedOutput;
 use crate::colours;
-use crate::parser::ast::{Action, TestSuiteSettings};
+use crate::parser::ast::{Action, CaptureSource, StdinSource, TestSuiteSettings};
 use crate::parser::helpers::substitute_variables_in_action;
-use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem};
+use portable_pty::{CommandBuilder, MasterPty, NativePtySystem, PtySize, PtySystem};
 use std::collections::HashMap;
-use std::io::Read;
+use std::fs::File;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
-use std::time::Duration;
-use terminal_size::{Height, Width, terminal_size};
+use std::time::{Duration, Instant};
 
-pub struct TerminalBackend {
-    pty_output_receiver: Receiver<String>,
+/// A process attached to a pseudo-terminal, with a thread forwarding everything it prints
+/// and keeping a model of the screen it draws.
+struct PtySession {
+    // Kept alive so the terminal stays open for as long as the session does.
+    #[allow(dead_code)]
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
     child: Box<dyn portable_pty::Child + Send + Sync>,
+    output_receiver: Receiver<String>,
+    screen: Arc<Mutex<vt100::Parser>>,
     #[allow(dead_code)]
     reader_thread: Option<JoinHandle<()>>,
-    // For non-interactive command execution (`runs`)
-    pub last_stdout: String,
-    pub last_stderr: String,
-    cwd: PathBuf,
-    settings: TestSuiteSettings,
 }
 
-impl TerminalBackend {
-    /// Creates a new backend with a PTY session.
-    /// - `base_dir`: The directory where the shell process should start.
-    /// - `shell_path`: An optional path to a specific shell executable.
-    pub fn new(cwd: PathBuf, settings: TestSuiteSettings) -> Self {
-        // Get the size of the user's actual terminal.
-        let term_size = terminal_size();
-        let (cols, rows) = if let Some((Width(w), Height(h))) = term_size {
-            (w, h)
-        } else {
-            // Provide a sensible default if the size can't be determined.
-            (100, 40)
-        };
+impl PtySession {
+    /// Opens a new PTY of the configured size and starts `cmd` inside it.
+    fn open(cmd: CommandBuilder, settings: &TestSuiteSettings) -> Self {
+        // A fixed size, rather than the size of the user's terminal, keeps screen assertions stable.
+        let (rows, cols) = (settings.terminal_rows, settings.terminal_cols);
         // Create a new PtySystem.
         let pty_system = NativePtySystem::default();
 
@@ -51,58 +51,202 @@ impl TerminalBackend {
             })
             .expect("Failed to open pty");
 
-        let shell_path = settings
-            .shell_path
-            .clone()
-            .unwrap_or_else(|| "/bin/sh".to_string());
-        let mut cmd = CommandBuilder::new(shell_path);
-        cmd.cwd(&cwd);
         let child = pair
             .slave
             .spawn_command(cmd)
             .expect("Failed to spawn command");
+        // The PTY's process starts its own session, and so its own process group.
+        if let Some(pid) = child.process_id() {
+            register_group(pid);
+        }
 
-        // We need to get a reader for the PTY's master end.
-        let reader = pair
+        // We need a reader and a writer for the PTY's master end.
+        let mut reader = pair
             .master
             .try_clone_reader()
             .expect("Failed to clone reader");
+        let writer = pair.master.take_writer().expect("Failed to take writer");
 
         // Create the channel for communication.
         let (sender, receiver): (Sender<String>, Receiver<String>) = mpsc::channel();
+        let screen = Arc::new(Mutex::new(vt100::Parser::new(rows, cols, 0)));
+        let reader_screen = Arc::clone(&screen);
 
         // Spawn the reader thread.
         let reader_thread = thread::spawn(move || {
             // This thread will block here, but it won't freeze the main program.
-            for byte in reader.bytes() {
-                let byte = byte.expect("PTY reader failed");
-                let char_str = String::from_utf8_lossy(&[byte]).to_string();
+            let mut chunk = [0u8; 4096];
+            let mut pending: Vec<u8> = Vec::new();
+            loop {
+                // Reading fails (EIO) once the process on the other end has exited.
+                let n = match reader.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                if let Ok(mut parser) = reader_screen.lock() {
+                    parser.process(&chunk[..n]);
+                }
+                pending.extend_from_slice(&chunk[..n]);
+
+                // Only forward complete UTF-8 sequences; keep a split character for the next read.
+                let valid_up_to = match std::str::from_utf8(&pending) {
+                    Ok(_) => pending.len(),
+                    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                    Err(_) => pending.len(),
+                };
+                let text = String::from_utf8_lossy(&pending[..valid_up_to]).to_string();
+                pending.drain(..valid_up_to);
 
                 // Only send non-empty strings through the channel.
-                if !char_str.is_empty() {
-                    if sender.send(char_str).is_err() {
-                        break;
-                    }
+                if !text.is_empty() && sender.send(text).is_err() {
+                    break;
                 }
             }
         });
 
         Self {
-            pty_output_receiver: receiver,
+            master: pair.master,
+            writer,
             child,
+            output_receiver: receiver,
+            screen,
             reader_thread: Some(reader_thread),
+        }
+    }
+}
+
+impl Drop for PtySession {
+    fn drop(&mut self) {
+        // Terminate the child process.
+        if let Err(e) = self.child.kill() {
+            eprintln!("Failed to kill child process: {}", e);
+        }
+        // Wait for the child process to exit.
+        let _ = self.child.wait();
+        if let Some(pid) = self.child.process_id() {
+            release_group(pid);
+        }
+    }
+}
+
+/// How the last `run` command ended, besides its exit code.
+#[derive(Debug, Clone, Default)]
+pub struct RunOutcome {
+    /// The signal that ended the command, if it was killed by one.
+    pub signal: Option<i32>,
+    pub timed_out: bool,
+    pub duration: Duration,
+}
+
+pub struct TerminalBackend {
+    session: PtySession,
+    // For non-interactive command execution (`runs`)
+    pub last_stdout: String,
+    pub last_stderr: String,
+    // The stdout of the last `run`, and its stdout and stderr in the order they were written.
+    // Unlike `last_stdout`, these are not moved into the PTY buffer.
+    pub run_stdout: String,
+    pub run_output: String,
+    pub last_run: Option<RunOutcome>,
+    cwd: PathBuf,
+    settings: TestSuiteSettings,
+    // Background processes started with `start ... as name`, stopped when the backend is dropped.
+    processes: HashMap<String, ManagedProcess>,
+    // Output of runs and of the PTY that has not been added to a test's report yet.
+    unrecorded: CapturedOutput,
+}
+
+impl TerminalBackend {
+    /// Creates a new backend with a PTY session.
+    /// - `base_dir`: The directory where the shell process should start.
+    /// - `shell_path`: An optional path to a specific shell executable.
+    pub fn new(cwd: PathBuf, settings: TestSuiteSettings) -> Self {
+        let shell_path = settings
+            .shell_path
+            .clone()
+            .unwrap_or_else(|| "/bin/sh".to_string());
+        let mut cmd = CommandBuilder::new(shell_path);
+        cmd.cwd(&cwd);
+        for (key, value) in &settings.env_overrides {
+            cmd.env(key, value);
+        }
+
+        Self {
+            session: PtySession::open(cmd, &settings),
             last_stdout: String::new(),
             last_stderr: String::new(),
+            run_stdout: String::new(),
+            run_output: String::new(),
+            last_run: None,
             cwd,
             settings,
+            processes: HashMap::new(),
+            unrecorded: CapturedOutput::default(),
+        }
+    }
+
+    pub fn has_process(&self, name: &str) -> bool {
+        self.processes.contains_key(name)
+    }
+
+    /// Returns the background process started under `name`, if any.
+    pub fn process_mut(&mut self, name: &str) -> Option<&mut ManagedProcess> {
+        self.processes.get_mut(name)
+    }
+
+    /// Returns true while the program in the interactive PTY session has not exited.
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.session.child.try_wait(), Ok(None))
+    }
+
+    /// The exit code of the program in the interactive PTY session, once it has exited.
+    pub fn exit_code(&mut self) -> Option<i32> {
+        match self.session.child.try_wait() {
+            Ok(Some(status)) => Some(status.exit_code() as i32),
+            _ => None,
+        }
+    }
+
+    /// The text on the interactive terminal's screen, one line per row, without trailing spaces
+    /// or trailing empty rows.
+    pub fn screen_lines(&self) -> Vec<String> {
+        let Ok(parser) = self.session.screen.lock() else {
+            return Vec::new();
+        };
+        let screen = parser.screen();
+        let (_, cols) = screen.size();
+        let mut lines: Vec<String> = screen
+            .rows(0, cols)
+            .map(|row| row.trim_end().to_string())
+            .collect();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+
+    /// The cursor position on the interactive terminal's screen as 1-based (row, column).
+    pub fn cursor_position(&self) -> Option<(u16, u16)> {
+        let parser = self.session.screen.lock().ok()?;
+        let (row, col) = parser.screen().cursor_position();
+        Some((row + 1, col + 1))
+    }
+
+    /// Writes raw bytes to the interactive PTY session.
+    fn write_to_pty(&mut self, bytes: &[u8]) {
+        let result = self
+            .session
+            .writer
+            .write_all(bytes)
+            .and_then(|_| self.session.writer.flush());
+        if let Err(e) = result {
+            colours::error(&format!("[TERMINAL] Failed to write to PTY: {}", e));
         }
     }
 
     /// Reads from the interactive PTY buffer. This is for `types` and `presses`.
     pub fn read_pty_output(&mut self, pty_buffer: &mut String) {
-        for new_output in self.pty_output_receiver.try_iter() {
-            pty_buffer.push_str(&new_output);
-        }
+        self.read_pty_stream(pty_buffer);
 
         // Append the stdout from the last non-interactive `run` command, if any.
         if !self.last_stdout.is_empty() {
@@ -116,24 +260,44 @@ impl TerminalBackend {
         }
     }
 
+    /// Moves any new output of the interactive PTY session into the buffer.
+    pub fn read_pty_stream(&mut self, pty_buffer: &mut String) {
+        for new_output in self.session.output_receiver.try_iter() {
+            pty_buffer.push_str(&new_output);
+            self.unrecorded.stdout.push_str(&new_output);
+        }
+    }
+
+    /// Takes the output produced since the last call, for the report of the running test.
+    pub fn take_output(&mut self) -> CapturedOutput {
+        std::mem::take(&mut self.unrecorded)
+    }
+
+    /// Forgets the output of the last command.
+    fn clear_output(&mut self) {
+        self.last_stdout.clear();
+        self.last_stderr.clear();
+        self.run_stdout.clear();
+        self.run_output.clear();
+    }
+
     /// Executes a single action from the AST. Returns true if the action was handled.
     pub fn execute_action(
         &mut self,
         action: &Action,
         last_exit_code: &mut Option<i32>,
         timeout: Option<Duration>,
-        _env_vars: &mut HashMap<String, String>,
+        env_vars: &mut HashMap<String, String>,
         verbose: bool,
     ) -> bool {
-        let action = substitute_variables_in_action(action, _env_vars);
+        let action = substitute_variables_in_action(action, env_vars);
         match action {
-            Action::SetCwd { path } => {
+            Action::SetCwd { path, .. } => {
                 let new_path = self.cwd.join(&path);
                 if new_path.is_dir() {
                     self.cwd = new_path.canonicalize().unwrap_or_else(|_| new_path.clone());
                     *last_exit_code = Some(0);
-                    self.last_stdout.clear();
-                    self.last_stderr.clear();
+                    self.clear_output();
                     if verbose {
                         colours::info(&format!(
                             "[TERMINAL] Working directory set to: {}",
@@ -142,7 +306,7 @@ impl TerminalBackend {
                     }
                 } else {
                     *last_exit_code = Some(1);
-                    self.last_stdout.clear();
+                    self.clear_output();
                     self.last_stderr = format!("set_cwd: no such directory: {}", path);
                     if verbose {
                         eprintln!(
@@ -153,7 +317,138 @@ impl TerminalBackend {
                 }
                 true
             }
-            Action::Run { command, .. } => {
+            Action::Type { text, .. } => {
+                // Output now comes from the PTY rather than the last `run`.
+                self.clear_output();
+                if verbose {
+                    colours::info(&format!("[TERMINAL] Typing: {:?}", text));
+                }
+                self.write_to_pty(text.as_bytes());
+                true
+            }
+            Action::Press { key, .. } => {
+                self.clear_output();
+                match key_sequence(&key) {
+                    Some(bytes) => {
+                        if verbose {
+                            colours::info(&format!("[TERMINAL] Pressing: {}", key));
+                        }
+                        self.write_to_pty(&bytes);
+                    }
+                    None => colours::error(&format!("[TERMINAL] Unknown key: '{}'", key)),
+                }
+                true
+            }
+            Action::Spawn { command, .. } => {
+                self.clear_output();
+                let shell = self.settings.shell_path.as_deref().unwrap_or("/bin/sh");
+                let mut cmd = CommandBuilder::new(shell);
+                cmd.arg("-c");
+                cmd.arg(&command);
+                cmd.cwd(&self.cwd);
+                for (key, value) in &self.settings.env_overrides {
+                    cmd.env(key, value);
+                }
+                if verbose {
+                    colours::info(&format!("[TERMINAL] Spawning in PTY: {}", command));
+                }
+                // Replacing the session ends the previous one and its process.
+                self.session = PtySession::open(cmd, &self.settings);
+                true
+            }
+            Action::Start { command, name, .. } => {
+                let shell = self.settings.shell_path.as_deref().unwrap_or("/bin/sh");
+                match ManagedProcess::start(
+                    shell,
+                    &command,
+                    &self.cwd,
+                    &self.settings.env_overrides,
+                ) {
+                    Ok(process) => {
+                        if verbose {
+                            colours::info(&format!(
+                                "[TERMINAL] Started '{}' as {} (pid {})",
+                                command,
+                                name,
+                                process.pid()
+                            ));
+                        }
+                        // Starting a name again replaces (and stops) the earlier process.
+                        self.processes.insert(name, process);
+                    }
+                    Err(e) => {
+                        colours::error(&format!("[TERMINAL] Failed to start '{}': {}", command, e))
+                    }
+                }
+                true
+            }
+            Action::Capture {
+                source, variable, ..
+            } => {
+                let value = match source {
+                    CaptureSource::Stdout => self.last_stdout.trim().to_string(),
+                    CaptureSource::Stderr => self.last_stderr.trim().to_string(),
+                    CaptureSource::ExitCode => {
+                        last_exit_code.map_or(String::new(), |code| code.to_string())
+                    }
+                };
+                if verbose {
+                    colours::info(&format!(
+                        "[TERMINAL] Captured {} '{}' as {}",
+                        source, value, variable
+                    ));
+                }
+                env_vars.insert(variable, value);
+                true
+            }
+            Action::Stop { name, .. } => {
+                match self.processes.get_mut(&name) {
+                    Some(process) => {
+                        let pids = process.stop(STOP_GRACE_PERIOD);
+                        if verbose {
+                            colours::info(&format!(
+                                "[TERMINAL] Stopped {} (PIDs: {})",
+                                name,
+                                format_pids(&pids)
+                            ));
+                        }
+                    }
+                    None => {
+                        colours::error(&format!("[TERMINAL] No process was started as '{}'", name))
+                    }
+                }
+                true
+            }
+            Action::SendSignal { signal, name, .. } => {
+                match (self.processes.get_mut(&name), signal_number(&signal)) {
+                    (Some(process), Some(number)) => {
+                        if verbose {
+                            colours::info(&format!(
+                                "[TERMINAL] Sending {} to {} (pid {})",
+                                signal_name(number),
+                                name,
+                                process.pid()
+                            ));
+                        }
+                        process.send_signal(number);
+                    }
+                    (None, _) => {
+                        colours::error(&format!("[TERMINAL] No process was started as '{}'", name))
+                    }
+                    (_, None) => {
+                        colours::error(&format!("[TERMINAL] Unknown signal: '{}'", signal))
+                    }
+                }
+                true
+            }
+            Action::Run {
+                command,
+                timeout: run_timeout,
+                env,
+                stdin,
+                capture_as,
+                ..
+            } => {
                 // Special handling for bare 'cd' to update the backend's CWD.
                 // Chained commands (e.g. "cd /tmp && git init") are passed
                 // through to the shell so that && / || / ; are honoured.
@@ -169,13 +464,13 @@ impl TerminalBackend {
                     if new_path.is_dir() {
                         self.cwd = new_path.canonicalize().unwrap_or_else(|_| new_path.clone());
                         *last_exit_code = Some(0);
-                        self.last_stdout.clear();
-                        self.last_stderr.clear();
+                        self.clear_output();
                     } else {
                         *last_exit_code = Some(1);
-                        self.last_stdout.clear();
+                        self.clear_output();
                         self.last_stderr = format!("cd: no such file or directory: {}", path_str);
                     }
+                    self.last_run = Some(RunOutcome::default());
                     return true;
                 }
 
@@ -198,81 +493,132 @@ impl TerminalBackend {
 
                 // Reset last command results
                 *last_exit_code = None;
-                self.last_stdout.clear();
-                self.last_stderr.clear();
+                self.last_run = None;
+                self.clear_output();
+
+                // Without any input, stdin is closed so commands waiting for it do not hang.
+                let stdin_stdio = match &stdin {
+                    None => Stdio::null(),
+                    Some(StdinSource::Text(_)) => Stdio::piped(),
+                    Some(StdinSource::File(path)) => match File::open(self.cwd.join(path)) {
+                        Ok(file) => Stdio::from(file),
+                        Err(e) => {
+                            *last_exit_code = Some(1);
+                            self.last_stderr =
+                                format!("with_stdin_file: cannot read {}: {}", path, e);
+                            return true;
+                        }
+                    },
+                };
 
                 let shell = self.settings.shell_path.as_deref().unwrap_or("/bin/sh");
-                let mut child = Command::new(shell)
-                    .arg("-c")
-                    .arg(choreo_command)
-                    .current_dir(&self.cwd)
-                    .stdin(Stdio::null()) // Prevent hanging on commands waiting for stdin
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()
-                    .expect("Failed to spawn command");
-
-                let mut stdout_handle = child.stdout.take().unwrap();
-                let mut stderr_handle = child.stderr.take().unwrap();
-
-                let stdout_thread = thread::spawn(move || {
-                    let mut buf = Vec::new();
-                    stdout_handle
-                        .read_to_end(&mut buf)
-                        .expect("Failed to read stdout");
-                    buf
-                });
+                let started = Instant::now();
+                // In a group of its own, a timed-out command can be stopped together with
+                // everything it started, which would otherwise keep the output pipes open.
+                let mut child = spawn_in_group(
+                    Command::new(shell)
+                        .arg("-c")
+                        .arg(choreo_command)
+                        .current_dir(&self.cwd)
+                        .envs(self.settings.env_overrides.iter().cloned())
+                        .envs(env)
+                        .stdin(stdin_stdio)
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::piped()),
+                )
+                .expect("Failed to spawn command");
 
-                let stderr_thread = thread::spawn(move || {
-                    let mut buf = Vec::new();
-                    stderr_handle
-                        .read_to_end(&mut buf)
-                        .expect("Failed to read stderr");
-                    buf
-                });
+                if let (Some(StdinSource::Text(text)), Some(mut stdin_handle)) =
+                    (stdin, child.stdin.take())
+                {
+                    // Written from a thread so a command that prints before reading cannot block us.
+                    // Dropping the handle afterwards closes stdin.
+                    thread::spawn(move || {
+                        let _ = stdin_handle.write_all(text.as_bytes());
+                    });
+                }
+
+                let streams =
+                    StreamReader::start(child.stdout.take().unwrap(), child.stderr.take().unwrap());
 
+                // A `within` on the command takes precedence over the suite's timeout.
+                let timeout = run_timeout.map(Duration::from_secs_f32).or(timeout);
+                let mut killed_pids = None;
                 let status = if let Some(t) = timeout {
                     // This is a crude way to poll for completion with a timeout.
-                    // A more robust solution might use `wait_timeout`.
+                    // The command is done once it has exited and closed its output: anything
+                    // it left running in the background (`sleep 20 & echo hi`) keeps the pipes open.
                     let start = std::time::Instant::now();
                     let mut status = None;
                     while start.elapsed() < t {
-                        match child.try_wait() {
-                            Ok(Some(s)) => {
-                                status = Some(s);
-                                break;
-                            }
-                            Ok(None) => {
-                                thread::sleep(Duration::from_millis(50));
-                                continue;
+                        if status.is_none() {
+                            match child.try_wait() {
+                                Ok(exited) => status = exited,
+                                Err(e) => panic!("Error attempting to wait for child: {}", e),
                             }
-                            Err(e) => panic!("Error attempting to wait for child: {}", e),
                         }
+                        if status.is_some() && streams.is_finished() {
+                            break;
+                        }
+                        thread::sleep(Duration::from_millis(50));
                     }
 
-                    if status.is_none() {
+                    if status.is_none() || !streams.is_finished() {
                         // If we get here, the process timed out.
-                        child.kill().expect("Failed to kill timed-out process");
-                        self.last_stderr = "Command timed out".to_string();
+                        let (pids, stopped) = stop_group(&mut child, STOP_GRACE_PERIOD);
+                        colours::error(&format!(
+                            "[TERMINAL] Command timed out after {:?}: {}. Killed PIDs: {}",
+                            t,
+                            command,
+                            format_pids(&pids)
+                        ));
+                        killed_pids = Some(pids);
+                        status = status.or(stopped);
                     }
                     status
                 } else {
                     // No timeout, wait indefinitely.
                     Some(child.wait().expect("Failed to wait on child"))
                 };
+                release_group(child.id());
+                self.last_run = Some(RunOutcome {
+                    signal: status.and_then(terminating_signal),
+                    timed_out: killed_pids.is_some(),
+                    duration: started.elapsed(),
+                });
 
-                let stdout_bytes = stdout_thread.join().unwrap();
-                let stderr_bytes = stderr_thread.join().unwrap();
+                let (stdout, stderr, interleaved) = if killed_pids.is_some() {
+                    streams.finish_within(STOP_GRACE_PERIOD)
+                } else {
+                    streams.finish()
+                };
+                self.last_stdout = stdout.clone();
+                self.last_stderr = stderr;
+                self.run_stdout = stdout;
+                self.run_output = interleaved;
+                *last_exit_code = status.and_then(exit_code_of);
+                if let Some(pids) = killed_pids {
+                    if !self.last_stderr.is_empty() && !self.last_stderr.ends_with('\n') {
+                        self.last_stderr.push('\n');
+                    }
+                    let message =
+                        format!("Command timed out (killed PIDs: {})", format_pids(&pids));
+                    self.last_stderr.push_str(&message);
+                    if !self.run_output.is_empty() && !self.run_output.ends_with('\n') {
+                        self.run_output.push('\n');
+                    }
+                    self.run_output.push_str(&message);
+                }
 
-                self.last_stdout = String::from_utf8_lossy(&stdout_bytes).to_string();
-                self.last_stderr = String::from_utf8_lossy(&stderr_bytes).to_string();
-                *last_exit_code = status.and_then(|s| s.code()).or_else(|| {
-                    if self.last_stderr == "Command timed out" {
-                        Some(137)
-                    } else {
-                        None
+                for (recorded, output) in [
+                    (&mut self.unrecorded.stdout, &self.last_stdout),
+                    (&mut self.unrecorded.stderr, &self.last_stderr),
+                ] {
+                    recorded.push_str(output);
+                    if !output.is_empty() && !output.ends_with('\n') {
+                        recorded.push('\n');
                     }
-                });
+                }
 
                 if verbose {
                     if self.last_stdout.trim().is_empty() {
@@ -285,6 +631,10 @@ impl TerminalBackend {
                     eprintln!("[TERMINAL] stderr:\n{}", self.last_stderr);
                 }
 
+                if let Some(variable) = capture_as {
+                    env_vars.insert(variable, self.last_stdout.trim().to_string());
+                }
+
                 true
             }
 
@@ -298,13 +648,125 @@ impl TerminalBackend {
     }
 }
 
-impl Drop for TerminalBackend {
-    fn drop(&mut self) {
-        // Terminate the child process.
-        if let Err(e) = self.child.kill() {
-            eprintln!("Failed to kill child process: {}", e);
+/// A named terminal actor: its own backend (PTY, cwd, stdout/stderr) plus the
+/// PTY output and exit code it has produced.
+pub struct TerminalSession {
+    pub backend: TerminalBackend,
+    pub output_buffer: String,
+    pub last_exit_code: Option<i32>,
+}
+
+impl TerminalSession {
+    /// Moves any new PTY output into the session's output buffer.
+    pub fn read_output(&mut self) {
+        self.backend.read_pty_stream(&mut self.output_buffer);
+    }
+}
+
+/// The named terminal actors of a scenario, such as `Server` and `Client`. A session is
+/// started the first time its actor is used. The default `Terminal` actor is not kept here.
+pub struct TerminalSessions {
+    cwd: PathBuf,
+    settings: TestSuiteSettings,
+    sessions: HashMap<String, TerminalSession>,
+}
+
+impl TerminalSessions {
+    pub fn new(cwd: PathBuf, settings: TestSuiteSettings) -> Self {
+        Self {
+            cwd,
+            settings,
+            sessions: HashMap::new(),
         }
-        // Wait for the child process to exit.
-        let _ = self.child.wait();
     }
+
+    /// Returns the actor's session, starting it if needed.
+    pub fn get_or_start(&mut self, actor: &str) -> &mut TerminalSession {
+        self.sessions
+            .entry(actor.to_string())
+            .or_insert_with(|| TerminalSession {
+                backend: TerminalBackend::new(self.cwd.clone(), self.settings.clone()),
+                output_buffer: String::new(),
+                last_exit_code: None,
+            })
+    }
+
+    /// Takes the output produced by every session since the last call, in order of actor name.
+    pub fn take_output(&mut self) -> CapturedOutput {
+        let mut sessions: Vec<_> = self.sessions.iter_mut().collect();
+        sessions.sort_by_key(|(actor, _)| *actor);
+        let mut output = CapturedOutput::default();
+        for (_, session) in sessions {
+            output.append(session.backend.take_output());
+        }
+        output
+    }
+
+    /// Removes the actor's session (starting it if needed), so it can be used while the
+    /// other sessions are borrowed. Hand it back with `put_back`.
+    pub fn take(&mut self, actor: &str) -> TerminalSession {
+        self.get_or_start(actor);
+        self.sessions.remove(actor).unwrap()
+    }
+
+    pub fn put_back(&mut self, actor: &str, session: TerminalSession) {
+        self.sessions.insert(actor.to_string(), session);
+    }
+
+    /// Returns the actor whose session started a background process as `name`, if any.
+    pub fn process_owner(&self, name: &str) -> Option<String> {
+        self.sessions
+            .iter()
+            .find(|(_, session)| session.backend.has_process(name))
+            .map(|(actor, _)| actor.clone())
+    }
+}
+
+/// Formats PIDs as a comma-separated list for messages.
+fn format_pids(pids: &[u32]) -> String {
+    pids.iter()
+        .map(|pid| pid.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns the bytes a terminal sends for a named key, such as `Enter`, `Up` or `Ctrl-C`.
+/// Names are case-insensitive, and any single character stands for itself.
+pub fn key_sequence(key: &str) -> Option<Vec<u8>> {
+    let name = key.to_ascii_lowercase();
+    let sequence: &[u8] = match name.as_str() {
+        "enter" | "return" => b"\r",
+        "tab" => b"\t",
+        "backspace" => b"\x7f",
+        "escape" | "esc" => b"\x1b",
+        "space" => b" ",
+        "up" => b"\x1b[A",
+        "down" => b"\x1b[B",
+        "right" => b"\x1b[C",
+        "left" => b"\x1b[D",
+        "home" => b"\x1b[H",
+        "end" => b"\x1b[F",
+        "insert" => b"\x1b[2~",
+        "delete" | "del" => b"\x1b[3~",
+        "pageup" => b"\x1b[5~",
+        "pagedown" => b"\x1b[6~",
+        _ => {
+            if let Some(letter) = name
+                .strip_prefix("ctrl-")
+                .or_else(|| name.strip_prefix("ctrl+"))
+            {
+                // Ctrl-A..Ctrl-Z map to 0x01..0x1A.
+                return match letter.as_bytes() {
+                    [c @ b'a'..=b'z'] => Some(vec![c - b'a' + 1]),
+                    _ => None,
+                };
+            }
+            let mut chars = key.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c.to_string().into_bytes()),
+                _ => None,
+            };
+        }
+    };
+    Some(sequence.to_vec())
 }
@@ -1,11 +1,19 @@
-use crate::parser::ast::{Action, Condition, Value};
+use crate::colours;
+use crate::parser::ast::{Action, Condition, HttpOptions, HttpPoll, TestSuiteSettings};
 use crate::parser::helpers::{substitute_string, substitute_variables_in_action};
+use cookie_store::CookieStore;
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Debug, Display};
-use ureq::http::{Response, StatusCode};
-use ureq::{Agent, Body};
+use std::thread;
+use std::time::{Duration, Instant};
+use ureq::http::{HeaderMap, Method, Request, Response, StatusCode};
+use ureq::{Agent, AsSendBody, Body};
+use url::Url;
+
+/// How many redirects a request follows by default before the last response is returned as it is.
+const MAX_REDIRECTS: usize = 10;
 
 #[derive(Debug)]
 enum CompatResult {
@@ -42,16 +50,33 @@ impl Display for CompatResult {
 #[derive(Debug, Clone, Default)]
 pub struct LastResponse {
     pub status: StatusCode,
+    pub headers: HeaderMap,
     pub body: String,
     pub message: Option<String>,
     pub response_time_ms: u128,
 }
 
+impl LastResponse {
+    /// All values of a response header, in the order they were received.
+    pub fn header_values(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect()
+    }
+}
+
 /// The backend responsible for handling web-based actions and conditions.
 #[derive(Debug)]
 pub struct WebBackend {
     agent: Agent,
     headers: HashMap<String, String>,
+    /// Cookies set by responses. They are sent with later requests that match their domain,
+    /// path and `Secure` flag until they expire.
+    cookies: CookieStore,
+    /// Whether requests follow redirects unless they set `follow_redirects`.
+    follow_redirects: bool,
     pub last_response: Option<LastResponse>,
 }
 
@@ -74,15 +99,22 @@ impl WebBackend {
     }
     /// Creates a new WebBackend with a persistent HTTP client.
     pub fn new() -> Self {
-        let config = Agent::config_builder().http_status_as_error(false).build();
-        let agent: Agent = config.into();
         Self {
-            agent,
+            agent: build_agent(None),
             headers: HashMap::new(),
+            cookies: CookieStore::default(),
+            follow_redirects: true,
             last_response: None,
         }
     }
 
+    /// Applies the suite's `http_timeout` and `http_follow_redirects` settings.
+    pub fn with_settings(mut self, settings: &TestSuiteSettings) -> Self {
+        self.agent = build_agent(settings.http_timeout.map(Duration::from_secs_f32));
+        self.follow_redirects = settings.http_follow_redirects;
+        self
+    }
+
     /// Executes a single web-related action. Returns true if the action was handled.
     pub fn execute_action(
         &mut self,
@@ -90,8 +122,17 @@ impl WebBackend {
         env_vars: &mut HashMap<String, String>,
         verbose: bool,
     ) -> bool {
-        self.last_response = None;
         let substituted_action = substitute_variables_in_action(action, env_vars);
+        if let Action::HttpGet {
+            url,
+            options,
+            until: Some(poll),
+        } = &substituted_action
+        {
+            self.poll(url, options, poll, env_vars, verbose);
+            return true;
+        }
+        self.last_response = None;
         let start_time = std::time::Instant::now();
         let result: Result<Response<Body>, ureq::Error> = match &substituted_action {
             Action::HttpSetHeader { key, value } => {
@@ -174,6 +215,16 @@ impl WebBackend {
                     }
                 }
 
+                let stored: Vec<(String, String)> = self
+                    .cookies
+                    .iter_any()
+                    .filter(|cookie| cookie.name() == key)
+                    .map(|cookie| (String::from(&cookie.domain), String::from(&cookie.path)))
+                    .collect();
+                for (domain, path) in stored {
+                    self.cookies.remove(&domain, &path, key);
+                }
+
                 if verbose {
                     println!("[WEB_BACKEND] Cleared cookie: {}", key);
                 }
@@ -188,80 +239,42 @@ impl WebBackend {
                     println!("[WEB_BACKEND] Clearing all HTTP cookies");
                 }
                 self.headers.remove("Cookie");
+                self.cookies.clear();
                 // This isn't a request but need to return a response
                 let response = Response::builder()
                     .status(200)
                     .body(Body::builder().data("choreo"));
                 Ok(response.expect("hmm"))
             }
-            Action::HttpGet { url, .. } => {
+            Action::HttpGet { url, options, .. } => {
                 if verbose {
                     println!("[WEB_BACKEND] Performing HTTP GET to: {}", url);
                 }
-
-                let mut request = self.agent.get(url);
-
-                // Add headers
-                for (key, value) in &self.headers {
-                    request = request.header(key, value);
-                }
-
-                request.call()
+                self.send(Method::GET, url, None, options)
             }
-            Action::HttpPost { url, body } => {
+            Action::HttpPost { url, body, options } => {
                 if verbose {
                     println!("[WEB_BACKEND] Performing HTTP POST to: {}", url);
                 }
-
-                let mut request = self.agent.post(url);
-
-                // Add headers
-                for (key, value) in &self.headers {
-                    request = request.header(key, value);
-                }
-
-                request.send(body)
+                self.send(Method::POST, url, Some(body), options)
             }
-            Action::HttpPut { url, body } => {
+            Action::HttpPut { url, body, options } => {
                 if verbose {
                     println!("[WEB_BACKEND] Performing HTTP PUT to: {}", url);
                 }
-
-                let mut request = self.agent.put(url);
-
-                // Add headers
-                for (key, value) in &self.headers {
-                    request = request.header(key, value);
-                }
-
-                request.send(body)
+                self.send(Method::PUT, url, Some(body), options)
             }
-            Action::HttpPatch { url, body } => {
+            Action::HttpPatch { url, body, options } => {
                 if verbose {
                     println!("[WEB_BACKEND] Performing HTTP PATCH to: {}", url);
                 }
-
-                let mut request = self.agent.patch(url);
-
-                for (key, value) in &self.headers {
-                    request = request.header(key, value);
-                }
-
-                request.send(body)
+                self.send(Method::PATCH, url, Some(body), options)
             }
-            Action::HttpDelete { url } => {
+            Action::HttpDelete { url, options } => {
                 if verbose {
                     println!("[WEB_BACKEND] Performing HTTP DELETE to: {}", url);
                 }
-
-                let mut request = self.agent.delete(url);
-
-                // Add headers
-                for (key, value) in &self.headers {
-                    request = request.header(key, value);
-                }
-
-                request.call()
+                self.send(Method::DELETE, url, None, options)
             }
             _ => return false,
         };
@@ -281,8 +294,8 @@ impl WebBackend {
 
         let mut process_response = |response: Response<Body>, message: String| {
             let status = response.status();
-            let content_type = response
-                .headers()
+            let headers = response.headers().clone();
+            let content_type = headers
                 .get("content-type")
                 .and_then(|v| v.to_str().ok())
                 .unwrap_or("")
@@ -305,6 +318,7 @@ impl WebBackend {
             let response_time_ms = start_time.elapsed().as_millis();
             self.last_response = Some(LastResponse {
                 status,
+                headers,
                 body: body_json.clone(),
                 message: Some(message.to_string()),
                 response_time_ms,
@@ -335,6 +349,7 @@ impl WebBackend {
                 let error_message = format!("[WEB_BACKEND] HTTP request failed: {}", e);
                 self.last_response = Some(LastResponse {
                     status: StatusCode::from_u16(599).unwrap(),
+                    headers: HeaderMap::new(),
                     body: error_message.clone(),
                     response_time_ms: 0,
                     message: Some(error_message),
@@ -344,6 +359,200 @@ impl WebBackend {
         true
     }
 
+    /// Repeats a GET request until the condition holds or the time is up. The last response is
+    /// kept either way, so the `then` conditions see it.
+    fn poll(
+        &mut self,
+        url: &str,
+        options: &HttpOptions,
+        poll: &HttpPoll,
+        env_vars: &mut HashMap<String, String>,
+        verbose: bool,
+    ) {
+        let request = Action::HttpGet {
+            url: url.to_string(),
+            options: options.clone(),
+            until: None,
+        };
+        let deadline = Instant::now() + Duration::from_secs_f32(poll.within);
+        let mut attempts = 1;
+        loop {
+            self.execute_action(&request, env_vars, verbose);
+            if self.check_condition(&poll.condition, env_vars, verbose) {
+                break;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                colours::warn(&format!(
+                    "[WEB_BACKEND] Gave up polling {} after {} attempts in {}s",
+                    url, attempts, poll.within
+                ));
+                break;
+            }
+            thread::sleep(remaining.min(Duration::from_secs_f32(poll.every)));
+            attempts += 1;
+        }
+        if verbose {
+            println!("[WEB_BACKEND] Polled {} {} time(s)", url, attempts);
+        }
+    }
+
+    /// The status and the start of the body of the last response, for failure messages.
+    pub fn describe_last_response(&self) -> Option<String> {
+        let response = self.last_response.as_ref()?;
+        let body: String = response.body.trim().chars().take(200).collect();
+        let ellipsis = if body.len() < response.body.trim().len() {
+            "..."
+        } else {
+            ""
+        };
+        Some(format!(
+            "last response: {} {}{}",
+            response.status, body, ellipsis
+        ))
+    }
+
+    /// Sends a request with the custom headers and the cookies for its URL. Redirects are
+    /// followed unless the options or settings say otherwise, storing the cookies of every
+    /// response on the way.
+    fn send(
+        &mut self,
+        method: Method,
+        url: &str,
+        body: Option<&str>,
+        options: &HttpOptions,
+    ) -> Result<Response<Body>, ureq::Error> {
+        let mut method = method;
+        let mut url = Url::parse(url).map_err(|e| ureq::Error::BadUri(e.to_string()))?;
+        if !options.query.is_empty() {
+            url.query_pairs_mut().extend_pairs(&options.query);
+        }
+        let mut body = body.map(str::to_string);
+        let max_redirects = match options.follow_redirects.unwrap_or(self.follow_redirects) {
+            true => options.max_redirects.unwrap_or(MAX_REDIRECTS),
+            false => 0,
+        };
+        // Headers given with the request replace custom headers of the same name.
+        let overridden = |key: &str| {
+            options
+                .headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(key))
+        };
+        // Custom headers, such as `Authorization`, are only sent to the origin of the request,
+        // not to another origin it redirects to.
+        let origin = url.origin();
+        let mut redirects = 0;
+        loop {
+            let mut request = Request::builder().method(method.clone()).uri(url.as_str());
+            if url.origin() == origin {
+                for (key, value) in &self.headers {
+                    if !key.eq_ignore_ascii_case("cookie") && !overridden(key) {
+                        request = request.header(key, value);
+                    }
+                }
+                for (key, value) in &options.headers {
+                    request = request.header(key, value);
+                }
+            }
+            if !overridden("cookie")
+                && let Some(cookies) = self.cookie_header(&url)
+            {
+                request = request.header("Cookie", cookies);
+            }
+            let response = match &body {
+                Some(body) => self.run(request.body(body.as_str())?, options.timeout)?,
+                None => self.run(request.body(())?, options.timeout)?,
+            };
+
+            let set_cookies = response
+                .headers()
+                .get_all("set-cookie")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .filter_map(|value| cookie_store::RawCookie::parse(value.to_string()).ok());
+            self.cookies.store_response_cookies(set_cookies, &url);
+
+            let location = response
+                .headers()
+                .get("location")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|location| url.join(location).ok());
+            let Some(location) = location.filter(|_| response.status().is_redirection()) else {
+                return Ok(response);
+            };
+            if redirects == max_redirects {
+                return Ok(response);
+            }
+            redirects += 1;
+            // 307 and 308 repeat the request; the others turn it into a GET without a body.
+            if !matches!(response.status().as_u16(), 307 | 308) && method != Method::HEAD {
+                method = Method::GET;
+                body = None;
+            }
+            url = location;
+        }
+    }
+
+    /// Runs a single request, with its own timeout if it has one.
+    fn run<S: AsSendBody>(
+        &self,
+        request: Request<S>,
+        timeout: Option<f32>,
+    ) -> Result<Response<Body>, ureq::Error> {
+        match timeout {
+            Some(seconds) => {
+                let request = self
+                    .agent
+                    .configure_request(request)
+                    .timeout_global(Some(Duration::from_secs_f32(seconds)))
+                    .build();
+                self.agent.run(request)
+            }
+            None => self.agent.run(request),
+        }
+    }
+
+    /// The `Cookie` header for a request to `url`: the stored cookies that match it, followed
+    /// by the ones set with `Web set_cookie`, which replace stored cookies of the same name.
+    fn cookie_header(&self, url: &Url) -> Option<String> {
+        let manual = self
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("cookie"))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or("");
+        let manual_names: Vec<&str> = manual
+            .split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, _)| name.trim())
+            .collect();
+        let cookies: Vec<String> = self
+            .cookies
+            .get_request_values(url)
+            .filter(|(name, _)| !manual_names.contains(name))
+            .map(|(name, value)| format!("{}={}", name, value))
+            .chain((!manual.is_empty()).then(|| manual.to_string()))
+            .collect();
+        (!cookies.is_empty()).then(|| cookies.join("; "))
+    }
+
+    /// The unexpired stored cookies called `name`, across all domains and paths.
+    pub fn cookie_values(&self, name: &str) -> Vec<&str> {
+        self.cookies
+            .iter_unexpired()
+            .filter(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value())
+            .collect()
+    }
+
+    /// Returns the value at a JSON Pointer path in the last response body, if any.
+    pub fn json_body_at(&self, path: &str) -> Option<JsonValue> {
+        let response = self.last_response.as_ref()?;
+        let json_body = serde_json::from_str::<JsonValue>(&response.body).ok()?;
+        json_body.pointer(path).cloned()
+    }
+
     /// Checks a single web-related condition against the last response.
     pub fn check_condition(
         &self,
@@ -406,6 +615,51 @@ impl WebBackend {
                 }
                 false
             }
+            Condition::ResponseHeaderExists { name } => {
+                !last_response.header_values(name).is_empty()
+            }
+            Condition::ResponseHeaderEquals { name, value } => {
+                let values = last_response.header_values(name);
+                if verbose {
+                    println!("[WEB_BACKEND] Response header '{}': {:?}", name, values);
+                }
+                values.contains(&value.as_str())
+            }
+            Condition::ResponseHeaderContains { name, value } => {
+                let values = last_response.header_values(name);
+                if verbose {
+                    println!("[WEB_BACKEND] Response header '{}': {:?}", name, values);
+                }
+                values.iter().any(|actual| actual.contains(value.as_str()))
+            }
+            Condition::ResponseHeaderMatches {
+                name,
+                regex,
+                capture_as,
+            } => {
+                let Ok(re) = regex::Regex::new(regex) else {
+                    return false;
+                };
+                let Some(captures) = last_response
+                    .header_values(name)
+                    .into_iter()
+                    .find_map(|value| re.captures(value))
+                else {
+                    return false;
+                };
+                if let (Some(var_name), Some(capture_group)) = (capture_as, captures.get(1)) {
+                    variables.insert(var_name.clone(), capture_group.as_str().to_string());
+                }
+                true
+            }
+            Condition::CookieExists { name } => !self.cookie_values(name).is_empty(),
+            Condition::CookieEquals { name, value } => {
+                let values = self.cookie_values(name);
+                if verbose {
+                    println!("[WEB_BACKEND] Cookie '{}': {:?}", name, values);
+                }
+                values.contains(&value.as_str())
+            }
             Condition::ResponseBodyEqualsJson { expected, ignored } => {
                 // This is the new closure to pre-process and fix malformed JSON strings.
                 let fix_json_escaping = |json_str: &str| -> String {
@@ -529,27 +783,14 @@ impl WebBackend {
             } => {
                 if let Ok(json_body) = serde_json::from_str::<JsonValue>(&last_response.body) {
                     if let Some(actual_value) = json_body.pointer(path) {
-                        // Convert the serde_json::Value to our AST Value for comparison.
-                        let our_value = match actual_value {
-                            JsonValue::String(s) => Value::String(s.clone()),
-                            JsonValue::Number(n) => {
-                                // Prefer integer if available, otherwise fall back to float -> i32
-                                if let Some(i) = n.as_i64() {
-                                    Value::Number(i as i32)
-                                } else {
-                                    Value::Number(n.as_f64().unwrap_or(0.0) as i32)
-                                }
-                            }
-                            JsonValue::Bool(b) => Value::Bool(*b),
-                            // Add other type conversions as needed.
-                            // I'm lacking Object, Array abd null - TODO
-                            _ => Value::String(actual_value.to_string()),
-                        };
-                        return &our_value == expected_value;
+                        return expected_value.matches_json(actual_value);
                     }
                 }
                 false
             }
+            Condition::JsonPathCompare { path, comparison } => comparison
+                .evaluate(self.json_body_at(path).as_ref())
+                .is_ok(),
             Condition::JsonPathCapture { path, capture_as } => {
                 if let Ok(json_body) = serde_json::from_str::<JsonValue>(&last_response.body) {
                     if let Some(value) = json_body.pointer(path) {
@@ -582,6 +823,17 @@ impl WebBackend {
     }
 }
 
+/// An HTTP client that returns error statuses as responses and leaves redirects to `send`, so
+/// that cookies set on the way are kept.
+fn build_agent(timeout: Option<Duration>) -> Agent {
+    Agent::config_builder()
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .timeout_global(timeout)
+        .build()
+        .into()
+}
+
 /// Recursively normalises a JSON value to a canonical form.
 fn normalise_json(value: &mut JsonValue) {
     match value {
@@ -0,0 +1,40 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// The temporary directory a scenario runs in when `sandbox = true`. It is deleted when
+/// dropped, unless [`Workspace::keep`] was called.
+pub struct Workspace {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Workspace {
+    /// Creates a fresh, empty directory under the system's temp directory.
+    pub fn create() -> io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("choreo-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path)?;
+        Ok(Self {
+            path: path.canonicalize()?,
+            keep: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Leaves the directory in place, e.g. to look at what a failed scenario left behind.
+    pub fn keep(&mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
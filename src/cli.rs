@@ -17,9 +17,32 @@ pub struct Cli {
 pub enum Commands {
     /// Run a choreography test suite.
     Run {
-        /// Path to the choreography test suite file.
-        #[arg(short, long, default_value = "test.chor")]
-        file: String,
+        /// Test suite files, directories or glob patterns (e.g. "tests/e2e/**/*.chor").
+        paths: Vec<String>,
+        /// Path to a choreography test suite file. Can be repeated.
+        /// Defaults to "test.chor" when no paths are given.
+        #[arg(short, long)]
+        file: Vec<String>,
+        /// Only run tests whose tags match the expression, e.g. "smoke and not slow".
+        /// Tests that do not match are reported as skipped.
+        #[arg(long)]
+        tags: Option<String>,
+        /// Only run the scenario(s) whose name matches exactly or as a regex.
+        #[arg(long)]
+        scenario: Option<String>,
+        /// Only run the test(s) whose name, or "scenario::test" name, matches exactly or as a regex.
+        /// Tests it depends on through `Test has_succeeded` are run as well.
+        #[arg(long)]
+        test: Option<String>,
+        /// Rewrite the snapshot files of `..._matches_snapshot` conditions that no longer match.
+        #[arg(long)]
+        update_snapshots: bool,
+        /// Report format, overriding the suites' `report_format` setting.
+        #[arg(long, value_parser = ["json", "junit"])]
+        report_format: Option<String>,
+        /// Directory the report is written to, overriding the suites' `report_path` setting.
+        #[arg(long)]
+        report_path: Option<String>,
         /// Enable verbose output for debugging.
         #[arg(long)]
         verbose: bool,
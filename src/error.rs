@@ -40,9 +40,21 @@ pub enum AppError {
     #[error("Pest parsing error: {0}")]
     PestParse(#[from] pest::error::Error<parser::parser::Rule>),
 
+    #[error("Invalid tag expression: {0}")]
+    TagExpression(String),
+
+    #[error("Failed to write report: {0}")]
+    ReportError(String),
+
+    #[error("Conflicting report settings: {0}")]
+    ReportConflict(String),
+
     #[error("{count} test(s) failed.")]
     TestsFailed { count: usize, expected: usize },
 
+    #[error("{failed} of {total} test suite(s) failed.")]
+    SuitesFailed { failed: usize, total: usize },
+
     #[error("Self-update error: {0}")]
     SelfUpdate(#[from] self_update::errors::Error),
 
@@ -0,0 +1,236 @@
+use crate::colours;
+use crate::error::AppError;
+use crate::parser::ast::{Condition, GivenStep, Scenario, StateCondition, TestCase};
+use regex::Regex;
+use std::collections::HashSet;
+
+/// A boolean expression over tags, e.g. `smoke and not (slow or @linux-only)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+impl TagExpr {
+    /// Parses a tag expression. `not` binds tighter than `and`, which binds tighter than `or`.
+    /// Tags may be written with or without the leading `@`.
+    pub fn parse(input: &str) -> Result<TagExpr, AppError> {
+        let tokens = tokenize(input);
+        let mut pos = 0;
+        let expr = parse_or(&tokens, &mut pos)?;
+        if let Some(token) = tokens.get(pos) {
+            return Err(AppError::TagExpression(format!(
+                "unexpected '{}' in '{}'",
+                token, input
+            )));
+        }
+        Ok(expr)
+    }
+
+    /// Returns true if the given set of tags satisfies the expression.
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            TagExpr::Tag(tag) => tags.iter().any(|t| t == tag),
+            TagExpr::Not(inner) => !inner.matches(tags),
+            TagExpr::And(left, right) => left.matches(tags) && right.matches(tags),
+            TagExpr::Or(left, right) => left.matches(tags) || right.matches(tags),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for c in input.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn parse_or(tokens: &[String], pos: &mut usize) -> Result<TagExpr, AppError> {
+    let mut left = parse_and(tokens, pos)?;
+    while tokens.get(*pos).is_some_and(|t| t == "or") {
+        *pos += 1;
+        let right = parse_and(tokens, pos)?;
+        left = TagExpr::Or(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_and(tokens: &[String], pos: &mut usize) -> Result<TagExpr, AppError> {
+    let mut left = parse_not(tokens, pos)?;
+    while tokens.get(*pos).is_some_and(|t| t == "and") {
+        *pos += 1;
+        let right = parse_not(tokens, pos)?;
+        left = TagExpr::And(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_not(tokens: &[String], pos: &mut usize) -> Result<TagExpr, AppError> {
+    let token = tokens
+        .get(*pos)
+        .ok_or_else(|| AppError::TagExpression("unexpected end of expression".to_string()))?;
+    *pos += 1;
+    match token.as_str() {
+        "not" => Ok(TagExpr::Not(Box::new(parse_not(tokens, pos)?))),
+        "(" => {
+            let expr = parse_or(tokens, pos)?;
+            if tokens.get(*pos).is_none_or(|t| t != ")") {
+                return Err(AppError::TagExpression("missing closing ')'".to_string()));
+            }
+            *pos += 1;
+            Ok(expr)
+        }
+        "and" | "or" | ")" => Err(AppError::TagExpression(format!(
+            "expected a tag but found '{}'",
+            token
+        ))),
+        tag => Ok(TagExpr::Tag(tag.trim_start_matches('@').to_string())),
+    }
+}
+
+/// A name given on the command line, matched exactly or as a regex against the whole name.
+/// A name that is not a valid regex, e.g. with an unclosed bracket, is only matched exactly.
+#[derive(Debug, Clone)]
+pub struct NamePattern {
+    raw: String,
+    regex: Option<Regex>,
+}
+
+impl NamePattern {
+    pub fn new(pattern: &str) -> NamePattern {
+        NamePattern {
+            raw: pattern.to_string(),
+            regex: Regex::new(&format!("^(?:{})$", pattern)).ok(),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        name == self.raw
+            || self
+                .regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(name))
+    }
+}
+
+/// Decides which tests of a suite are selected to run.
+#[derive(Debug, Clone, Default)]
+pub struct TestFilter {
+    pub tags: Option<TagExpr>,
+    /// Matched against the scenario name.
+    pub scenario: Option<NamePattern>,
+    /// Matched against the test name or its scoped `scenario::test` name.
+    pub test: Option<NamePattern>,
+}
+
+impl TestFilter {
+    /// Returns true if no filtering has been requested.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_none() && self.scenario.is_none() && self.test.is_none()
+    }
+
+    /// Returns the names of the tests in the scenario that should run.
+    ///
+    /// Tests picked by `--scenario`/`--test` pull in the tests they depend on through
+    /// `Test has_succeeded`. Tests not matching `--tags` are always left out, and so is any
+    /// test whose dependency was left out, with a warning naming the missing dependency.
+    pub fn select<'a>(&self, scenario: &Scenario, tests: &'a [TestCase]) -> HashSet<&'a str> {
+        let mut selected: HashSet<&str> = tests
+            .iter()
+            .filter(|t| self.matches_name(scenario, t))
+            .map(|t| t.name.as_str())
+            .collect();
+
+        // Pull in dependencies of the selected tests, following chains of dependencies.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for test in tests {
+                if !selected.contains(test.name.as_str()) {
+                    continue;
+                }
+                for dep in dependencies(test) {
+                    if let Some(dep_test) = tests.iter().find(|t| t.name == dep) {
+                        changed |= selected.insert(dep_test.name.as_str());
+                    }
+                }
+            }
+        }
+
+        selected.retain(|name| {
+            tests
+                .iter()
+                .find(|t| t.name == *name)
+                .is_some_and(|t| self.matches_tags(scenario, t))
+        });
+
+        // Drop tests whose dependency was filtered out, until no more are found.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for test in tests {
+                if !selected.contains(test.name.as_str()) {
+                    continue;
+                }
+                let missing = dependencies(test)
+                    .find(|dep| tests.iter().any(|t| t.name == *dep) && !selected.contains(dep));
+                if let Some(dep) = missing {
+                    colours::warn(&format!(
+                        "Test '{}' depends on '{}', which was filtered out. Skipping '{}' as well.",
+                        test.name, dep, test.name
+                    ));
+                    selected.remove(test.name.as_str());
+                    changed = true;
+                }
+            }
+        }
+
+        selected
+    }
+
+    fn matches_name(&self, scenario: &Scenario, test: &TestCase) -> bool {
+        let scenario_matches = self
+            .scenario
+            .as_ref()
+            .is_none_or(|p| p.matches(&scenario.name));
+        let test_matches = self.test.as_ref().is_none_or(|p| {
+            p.matches(&test.name) || p.matches(&format!("{}::{}", scenario.name, test.name))
+        });
+        scenario_matches && test_matches
+    }
+
+    fn matches_tags(&self, scenario: &Scenario, test: &TestCase) -> bool {
+        match &self.tags {
+            Some(expr) => {
+                let tags: Vec<String> = scenario.tags.iter().chain(&test.tags).cloned().collect();
+                expr.matches(&tags)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Names of the tests this test waits for through `Test has_succeeded` in its `given` block.
+fn dependencies(test: &TestCase) -> impl Iterator<Item = &str> {
+    test.given.iter().filter_map(|step| match step {
+        GivenStep::Condition(Condition::State(StateCondition::HasSucceeded(dep)))
+        | GivenStep::Condition(Condition::StateSucceeded { outcome: dep }) => Some(dep.as_str()),
+        _ => None,
+    })
+}
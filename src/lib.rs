@@ -2,6 +2,7 @@ pub mod backend;
 pub mod cli;
 pub mod colours;
 pub mod error;
+pub mod filter;
 pub mod parser;
 pub mod reporting;
 pub mod runner;
@@ -1,15 +1,19 @@
+use choreo::backend::process;
 use choreo::cli;
 use choreo::cli::{Cli, Commands};
 use choreo::colours;
 use choreo::error::AppError;
-use choreo::parser::ast::{Statement, TaskDef, Value};
+use choreo::filter::{NamePattern, TagExpr, TestFilter};
+use choreo::parser::ast::{ReportFormat, Scenario, Statement, TaskDef, Value};
 use choreo::parser::helpers::substitute_string;
 use choreo::parser::{linter, parser};
-use choreo::runner::TestRunner;
+use choreo::reporting::{ReportTarget, SuiteError, generate_choreo_report};
+use choreo::runner::{SuiteResult, TestRunner};
 use clap::Parser;
 use colored::Colorize;
 use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, fs};
 
 const INIT_TEMPLATE: &str = r#"# A test suite for your application
@@ -175,6 +179,290 @@ fn enhance_parse_error<E: ToString>(err: E, source: &str) -> String {
     }
 }
 
+/// Parses a test suite file, resolves its imports and prepares a runner for it.
+fn load_suite(
+    file: &Path,
+    filter: &TestFilter,
+    verbose: bool,
+) -> Result<(TestRunner, Vec<Scenario>), AppError> {
+    if verbose {
+        colours::info(&format!("Starting Choreo Test Runner: {}", file.display()));
+    }
+
+    let source = fs::read_to_string(file)?;
+    let test_suite = match parser::parse(&source) {
+        Ok(suite) => {
+            if verbose {
+                colours::success("Test suite parsed successfully.");
+            }
+            suite
+        }
+        Err(e) => {
+            // Return an AppError::ParseError with extra context/hint
+            return Err(AppError::ParseError(enhance_parse_error(e, &source)));
+        }
+    };
+
+    let mut env_vars: HashMap<String, String> = HashMap::new();
+    let mut scenarios: Vec<Scenario> = Vec::new();
+    let mut tasks: HashMap<String, TaskDef> = HashMap::new();
+    let base_dir = file
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| std::path::Path::new("."));
+
+    // Resolve imports first to make imported tasks/vars available
+    {
+        let mut visited = HashSet::new();
+        let imported_statements =
+            resolve_imports(&test_suite.statements, &base_dir, &mut visited, verbose)?;
+
+        for s in imported_statements {
+            match s {
+                Statement::TaskDef(task_def) => {
+                    tasks.insert(task_def.name.clone(), task_def.clone());
+                }
+                Statement::VarDef(name, value) => match value {
+                    Value::Array(arr) => {
+                        // Convert array to JSON string for proper substitution
+                        let json_array = serde_json::to_string(
+                            &arr.iter().map(|v| v.as_string()).collect::<Vec<_>>(),
+                        )
+                        .unwrap_or_else(|_| "[]".to_string());
+                        let substituted_value = substitute_string(&json_array, &env_vars);
+                        env_vars.insert(name.clone(), substituted_value.clone());
+                        //env_vars.insert(format!("${{{}}}", name), substituted_value);
+                    }
+                    _ => {
+                        let substituted_value = substitute_string(&value.as_string(), &env_vars);
+                        env_vars.insert(name.clone(), substituted_value.clone());
+                        //env_vars.insert(format!("${{{}}}", name), substituted_value);
+                    }
+                },
+                _ => {} // Ignore other statement types
+            }
+        }
+    }
+
+    for s in &test_suite.statements {
+        match s {
+            Statement::TaskDef(task_def) => {
+                tasks.insert(task_def.name.clone(), task_def.clone());
+            }
+            Statement::BackgroundDef(steps) => {
+                // Convert background steps to a scenario
+                let bg_test_case = choreo::parser::ast::TestCase {
+                    name: "Background Setup".to_string(),
+                    description: "Setup steps from Background".to_string(),
+                    given: steps.clone(),
+                    when: vec![],
+                    then: vec![],
+                    tags: vec![],
+                    span: None,
+                    testcase_spans: None,
+                };
+                let bg_scenario = choreo::parser::ast::Scenario {
+                    name: "Background".to_string(),
+                    tests: vec![bg_test_case.clone()],
+                    body: vec![choreo::parser::ast::ScenarioBodyItem::Test(bg_test_case)],
+                    after: vec![],
+                    parallel: false,
+                    tags: vec![],
+                    examples: None,
+                    scenario_span: None,
+                    span: None,
+                };
+                scenarios.insert(0, bg_scenario); // Ensure background is first
+            }
+            Statement::EnvDef(vars) => {
+                for var in vars {
+                    let value = env::var(var).map_err(|_| AppError::EnvVarNotFound(var.clone()))?;
+                    env_vars.insert(var.clone(), value);
+                }
+            }
+            Statement::VarDef(name, value) => match value {
+                Value::Array(arr) => {
+                    // Convert array to JSON string for proper substitution
+                    let json_array = serde_json::to_string(
+                        &arr.iter().map(|v| v.as_string()).collect::<Vec<_>>(),
+                    )
+                    .unwrap_or_else(|_| "[]".to_string());
+                    let substituted_value = substitute_string(&json_array, &env_vars);
+                    env_vars.insert(name.clone(), substituted_value.clone());
+                    //env_vars.insert(format!("${{{}}}", name), substituted_value);
+                }
+                _ => {
+                    let substituted_value = substitute_string(&value.as_string(), &env_vars);
+                    env_vars.insert(name.clone(), substituted_value.clone());
+                    //env_vars.insert(format!("${{{}}}", name), substituted_value);
+                }
+            },
+            Statement::Scenario(scenario) => scenarios.push(scenario.clone()),
+            _ => {} // Ignore other statement types
+        }
+    }
+
+    let runner = TestRunner::new(
+        test_suite,
+        base_dir.to_path_buf(),
+        env_vars.clone(),
+        tasks,
+        verbose,
+    )
+    .with_filter(filter.clone());
+
+    Ok((runner, scenarios))
+}
+
+/// Expands the given paths into a sorted, de-duplicated list of test suite files.
+/// Directories are searched recursively for `.chor` files and glob patterns are expanded.
+fn collect_suite_files(paths: &[String]) -> Result<Vec<PathBuf>, AppError> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        let p = Path::new(path);
+        if p.is_dir() {
+            let mut found = Vec::new();
+            find_chor_files(p, &mut found)?;
+            if found.is_empty() {
+                return Err(AppError::FileNotFound(format!(
+                    "No .chor files in directory '{}'",
+                    path
+                )));
+            }
+            found.sort();
+            files.extend(found);
+        } else if p.exists() {
+            files.push(p.to_path_buf());
+        } else if path.contains(['*', '?', '[']) {
+            let pattern = glob::glob(path).map_err(|e| {
+                AppError::FileNotFound(format!("Invalid pattern '{}': {}", path, e))
+            })?;
+            let matched: Vec<PathBuf> = pattern
+                .filter_map(Result::ok)
+                .filter(|p| p.is_file())
+                .collect();
+            if matched.is_empty() {
+                return Err(AppError::FileNotFound(format!(
+                    "No files match pattern '{}'",
+                    path
+                )));
+            }
+            files.extend(matched);
+        } else {
+            return Err(AppError::FileNotFound(path.clone()));
+        }
+    }
+
+    if files.is_empty() {
+        return Err(AppError::FileNotFound("No test suites given".to_string()));
+    }
+
+    let mut seen = HashSet::new();
+    files.retain(|f| seen.insert(f.canonicalize().unwrap_or_else(|_| f.clone())));
+    Ok(files)
+}
+
+fn find_chor_files(dir: &Path, found: &mut Vec<PathBuf>) -> Result<(), AppError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_chor_files(&path, found)?;
+        } else if path.extension().is_some_and(|ext| ext == "chor") {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Runs each suite in turn, then prints an aggregated summary and writes one combined report.
+fn run_suites(
+    files: &[PathBuf],
+    filter: &TestFilter,
+    update_snapshots: bool,
+    report_format: Option<ReportFormat>,
+    report_path: Option<String>,
+    verbose: bool,
+) -> Result<(), AppError> {
+    let mut results = Vec::new();
+    let mut errors = Vec::new();
+    let mut failed_suites = Vec::new();
+
+    // Load every suite first, so conflicting report settings are rejected before anything runs.
+    let mut loaded = Vec::new();
+    for file in files {
+        let suite_name = file.display().to_string();
+        match load_suite(file, filter, verbose) {
+            Ok(suite) => loaded.push((suite_name, suite)),
+            Err(e) => {
+                colours::error(&format!("Error in '{}': {}", suite_name, e));
+                failed_suites.push(suite_name.clone());
+                errors.push(SuiteError {
+                    suite_name,
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+
+    let settings: Vec<_> = loaded
+        .iter()
+        .map(|(name, (runner, _))| (name.as_str(), runner.settings()))
+        .collect();
+    let target = ReportTarget::resolve(&settings, report_format, report_path)?;
+
+    for (suite_name, (runner, scenarios)) in loaded {
+        let mut runner = runner.with_update_snapshots(update_snapshots);
+        match runner.run_suite(&suite_name, &scenarios) {
+            Ok(result) => {
+                if result.has_unexpected_failures() {
+                    failed_suites.push(suite_name);
+                }
+                results.push(result);
+            }
+            Err(e) => {
+                colours::error(&format!("Error in '{}': {}", suite_name, e));
+                failed_suites.push(suite_name.clone());
+                errors.push(SuiteError {
+                    suite_name,
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+
+    generate_choreo_report(&results, &errors, &target, verbose)?;
+
+    let total = |f: fn(&SuiteResult) -> usize| results.iter().map(f).sum::<usize>();
+    colours::info(&format!(
+        "\nOverall summary: {} suite(s), {} tests run in {:.2}s ({} passed, {} failed, {} skipped)",
+        files.len(),
+        total(|r| r.test_states.len()),
+        results
+            .iter()
+            .map(|r| r.duration)
+            .sum::<Duration>()
+            .as_secs_f32(),
+        total(SuiteResult::passed),
+        total(SuiteResult::failed),
+        total(SuiteResult::skipped)
+    ));
+
+    if failed_suites.is_empty() {
+        colours::success("All test suites passed.");
+        return Ok(());
+    }
+
+    colours::error("Failed test suites:");
+    for name in &failed_suites {
+        println!("- {}", name);
+    }
+    Err(AppError::SuitesFailed {
+        failed: failed_suites.len(),
+        total: files.len(),
+    })
+}
+
 fn main() {
     let cli = cli::Cli::parse();
     if let Err(e) = run(cli) {
@@ -186,137 +474,52 @@ fn main() {
 // The main logic function, which takes the parsed CLI commands
 pub fn run(cli: Cli) -> Result<(), AppError> {
     match cli.command {
-        Commands::Run { file, verbose } => {
-            let suite_name = file.clone();
-
-            if verbose {
-                colours::info(&format!("Starting Choreo Test Runner: {}", file));
+        Commands::Run {
+            paths,
+            file,
+            tags,
+            scenario,
+            test,
+            update_snapshots,
+            report_format,
+            report_path,
+            verbose,
+        } => {
+            // Background processes run in their own process groups, so Ctrl-C does not reach them.
+            process::install_interrupt_handler();
+            let mut inputs = paths;
+            inputs.extend(file);
+            if inputs.is_empty() {
+                inputs.push("test.chor".to_string());
             }
-
-            let source = fs::read_to_string(&file)?;
-            let test_suite = match parser::parse(&source) {
-                Ok(suite) => {
-                    if verbose {
-                        colours::success("Test suite parsed successfully.");
-                    }
-                    suite
-                }
-                Err(e) => {
-                    // Return an AppError::ParseError with extra context/hint
-                    return Err(AppError::ParseError(enhance_parse_error(e, &source)));
-                }
+            let files = collect_suite_files(&inputs)?;
+            let filter = TestFilter {
+                tags: tags.as_deref().map(TagExpr::parse).transpose()?,
+                scenario: scenario.as_deref().map(NamePattern::new),
+                test: test.as_deref().map(NamePattern::new),
             };
+            let report_format = report_format.map(|f| match f.as_str() {
+                "junit" => ReportFormat::Junit,
+                _ => ReportFormat::Json,
+            });
 
-            let mut env_vars: HashMap<String, String> = HashMap::new();
-            let mut scenarios: Vec<choreo::parser::ast::Scenario> = Vec::new();
-            let mut tasks: HashMap<String, TaskDef> = HashMap::new();
-            let test_file_path = std::path::Path::new(&file);
-            let base_dir = test_file_path
-                .parent()
-                .filter(|p| !p.as_os_str().is_empty())
-                .unwrap_or_else(|| std::path::Path::new("."));
-
-            // Resolve imports first to make imported tasks/vars available
-            {
-                let mut visited = HashSet::new();
-                let imported_statements =
-                    resolve_imports(&test_suite.statements, &base_dir, &mut visited, verbose)?;
-
-                for s in imported_statements {
-                    match s {
-                        Statement::TaskDef(task_def) => {
-                            tasks.insert(task_def.name.clone(), task_def.clone());
-                        }
-                        Statement::VarDef(name, value) => match value {
-                            Value::Array(arr) => {
-                                // Convert array to JSON string for proper substitution
-                                let json_array = serde_json::to_string(
-                                    &arr.iter().map(|v| v.as_string()).collect::<Vec<_>>(),
-                                )
-                                .unwrap_or_else(|_| "[]".to_string());
-                                let substituted_value = substitute_string(&json_array, &env_vars);
-                                env_vars.insert(name.clone(), substituted_value.clone());
-                                //env_vars.insert(format!("${{{}}}", name), substituted_value);
-                            }
-                            _ => {
-                                let substituted_value =
-                                    substitute_string(&value.as_string(), &env_vars);
-                                env_vars.insert(name.clone(), substituted_value.clone());
-                                //env_vars.insert(format!("${{{}}}", name), substituted_value);
-                            }
-                        },
-                        _ => {} // Ignore other statement types
-                    }
+            match files.as_slice() {
+                [single] => {
+                    let (runner, scenarios) = load_suite(single, &filter, verbose)?;
+                    let mut runner = runner
+                        .with_update_snapshots(update_snapshots)
+                        .with_report_overrides(report_format, report_path);
+                    runner.run(&single.display().to_string(), &scenarios)
                 }
+                _ => run_suites(
+                    &files,
+                    &filter,
+                    update_snapshots,
+                    report_format,
+                    report_path,
+                    verbose,
+                ),
             }
-
-            for s in &test_suite.statements {
-                match s {
-                    Statement::TaskDef(task_def) => {
-                        tasks.insert(task_def.name.clone(), task_def.clone());
-                    }
-                    Statement::BackgroundDef(steps) => {
-                        // Convert background steps to a scenario
-                        let bg_test_case = choreo::parser::ast::TestCase {
-                            name: "Background Setup".to_string(),
-                            description: "Setup steps from Background".to_string(),
-                            given: steps.clone(),
-                            when: vec![],
-                            then: vec![],
-                            span: None,
-                            testcase_spans: None,
-                        };
-                        let bg_scenario = choreo::parser::ast::Scenario {
-                            name: "Background".to_string(),
-                            tests: vec![bg_test_case.clone()],
-                            body: vec![choreo::parser::ast::ScenarioBodyItem::Test(bg_test_case)],
-                            after: vec![],
-                            parallel: false,
-                            scenario_span: None,
-                            span: None,
-                        };
-                        scenarios.insert(0, bg_scenario); // Ensure background is first
-                    }
-                    Statement::EnvDef(vars) => {
-                        for var in vars {
-                            let value =
-                                env::var(var).map_err(|_| AppError::EnvVarNotFound(var.clone()))?;
-                            env_vars.insert(var.clone(), value);
-                        }
-                    }
-                    Statement::VarDef(name, value) => match value {
-                        Value::Array(arr) => {
-                            // Convert array to JSON string for proper substitution
-                            let json_array = serde_json::to_string(
-                                &arr.iter().map(|v| v.as_string()).collect::<Vec<_>>(),
-                            )
-                            .unwrap_or_else(|_| "[]".to_string());
-                            let substituted_value = substitute_string(&json_array, &env_vars);
-                            env_vars.insert(name.clone(), substituted_value.clone());
-                            //env_vars.insert(format!("${{{}}}", name), substituted_value);
-                        }
-                        _ => {
-                            let substituted_value =
-                                substitute_string(&value.as_string(), &env_vars);
-                            env_vars.insert(name.clone(), substituted_value.clone());
-                            //env_vars.insert(format!("${{{}}}", name), substituted_value);
-                        }
-                    },
-                    Statement::Scenario(scenario) => scenarios.push(scenario.clone()),
-                    _ => {} // Ignore other statement types
-                }
-            }
-
-            let mut runner = TestRunner::new(
-                test_suite,
-                base_dir.to_path_buf(),
-                env_vars.clone(),
-                tasks,
-                verbose,
-            );
-
-            // Call the runner and return its result
-            runner.run(&suite_name, &scenarios)
         }
         Commands::Init { file } => {
             if std::path::Path::new(&file).exists() {
@@ -1,3 +1,4 @@
+use crate::backend::snapshot::DEFAULT_REDACTIONS;
 use std::collections::HashMap;
 use std::fmt;
 
@@ -18,7 +19,10 @@ pub enum TestState {
 
 impl TestState {
     pub fn is_done(&self) -> bool {
-        matches!(self, TestState::Passed | TestState::Failed(_))
+        matches!(
+            self,
+            TestState::Passed | TestState::Failed(_) | TestState::Skipped
+        )
     }
 
     pub fn is_failed(&self) -> bool {
@@ -56,6 +60,13 @@ pub struct SettingSpan {
     pub shell_path_span: Option<Span>,
     pub stop_on_failure_span: Option<Span>,
     pub expected_failures_span: Option<Span>,
+    pub env_overrides_span: Option<Span>,
+    pub terminal_rows_span: Option<Span>,
+    pub terminal_cols_span: Option<Span>,
+    pub snapshot_redactions_span: Option<Span>,
+    pub sandbox_span: Option<Span>,
+    pub http_timeout_span: Option<Span>,
+    pub http_follow_redirects_span: Option<Span>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -66,6 +77,19 @@ pub struct TestSuiteSettings {
     pub stop_on_failure: bool,
     pub shell_path: Option<String>,
     pub expected_failures: usize,
+    /// Environment variables set for every command choreo starts, including the PTY shell.
+    pub env_overrides: Vec<(String, String)>,
+    /// Size of the interactive terminal (PTY) that `spawn`, `types` and `presses` use.
+    pub terminal_rows: u16,
+    pub terminal_cols: u16,
+    /// Redaction rules applied to output before it is compared with a snapshot.
+    pub snapshot_redactions: Vec<String>,
+    /// Runs each scenario in its own temporary directory, available as `${WORKSPACE}`.
+    pub sandbox: bool,
+    /// Seconds a web request may take. There is no limit by default.
+    pub http_timeout: Option<f32>,
+    /// Whether web requests follow redirects, storing the cookies set on the way.
+    pub http_follow_redirects: bool,
     pub span: Option<Span>,
     pub setting_spans: Option<SettingSpan>,
 }
@@ -79,6 +103,13 @@ impl Default for TestSuiteSettings {
             stop_on_failure: false,
             shell_path: Option::from("/bin/sh".to_string()),
             expected_failures: 0,
+            env_overrides: Vec::new(),
+            terminal_rows: 24,
+            terminal_cols: 80,
+            snapshot_redactions: DEFAULT_REDACTIONS.map(String::from).to_vec(),
+            sandbox: false,
+            http_timeout: None,
+            http_follow_redirects: true,
             span: None,
             setting_spans: None,
         }
@@ -92,6 +123,7 @@ pub struct TestCase {
     pub given: Vec<GivenStep>,
     pub when: Vec<WhenStep>,
     pub then: Vec<ThenStep>,
+    pub tags: Vec<String>,
     pub span: Option<Span>,
     pub testcase_spans: Option<TestCaseSpan>,
 }
@@ -113,6 +145,7 @@ impl Default for TestCase {
             given: Vec::new(),
             when: Vec::new(),
             then: Vec::new(),
+            tags: Vec::new(),
             span: None,
             testcase_spans: None,
         }
@@ -191,6 +224,32 @@ pub struct ForeachBlock {
 pub enum ScenarioBodyItem {
     Test(TestCase),
     Foreach(ForeachBlock),
+    Outline(TestOutline),
+}
+
+/// A Gherkin-style examples table. Each row is expanded into its own test or scenario,
+/// with `${column}` replaced by the row's value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ExamplesTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl ExamplesTable {
+    /// Pairs each column header with the row's value for that column.
+    pub fn bindings<'a>(
+        &'a self,
+        row: &'a [String],
+    ) -> impl Iterator<Item = (&'a String, &'a String)> {
+        self.headers.iter().zip(row)
+    }
+}
+
+/// A `test outline`: a test template expanded once per examples row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestOutline {
+    pub test: TestCase,
+    pub examples: ExamplesTable,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -200,6 +259,9 @@ pub struct Scenario {
     pub body: Vec<ScenarioBodyItem>,
     pub after: Vec<WhenStep>,
     pub parallel: bool,
+    pub tags: Vec<String>,
+    /// Set for a `scenario outline`, which is expanded into one scenario per row.
+    pub examples: Option<ExamplesTable>,
     pub span: Option<Span>,
     pub scenario_span: Option<ScenarioSpan>,
 }
@@ -212,6 +274,8 @@ impl Default for Scenario {
             body: Vec::new(),
             after: Vec::new(),
             parallel: false,
+            tags: Vec::new(),
+            examples: None,
             span: None,
             scenario_span: None,
         }
@@ -231,9 +295,99 @@ pub enum StateCondition {
     CanStart,
 }
 
+/// A check applied to the value at a JSON path, beyond plain equality.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonComparison {
+    GreaterThan(Value),
+    LessThan(Value),
+    /// Inclusive on both ends.
+    Between(Value, Value),
+    NotEquals(Value),
+    Matches(String),
+    IsNull,
+}
+
+impl JsonComparison {
+    /// Checks the value found at the path (if any). On failure, returns a message
+    /// describing what was expected and the actual value.
+    pub fn evaluate(&self, actual: Option<&serde_json::Value>) -> Result<(), String> {
+        let Some(actual) = actual else {
+            return Err(format!(
+                "expected a value that {}, but the path does not exist",
+                self
+            ));
+        };
+        let passed = match self {
+            JsonComparison::GreaterThan(limit) => {
+                numbers(actual, limit).is_some_and(|(actual, limit)| actual > limit)
+            }
+            JsonComparison::LessThan(limit) => {
+                numbers(actual, limit).is_some_and(|(actual, limit)| actual < limit)
+            }
+            JsonComparison::Between(low, high) => {
+                numbers(actual, low).is_some_and(|(actual, low)| actual >= low)
+                    && numbers(actual, high).is_some_and(|(actual, high)| actual <= high)
+            }
+            JsonComparison::NotEquals(value) => !value.matches_json(actual),
+            JsonComparison::Matches(pattern) => {
+                let regex = regex::Regex::new(pattern)
+                    .map_err(|e| format!("invalid regex '{}': {}", pattern, e))?;
+                match actual {
+                    serde_json::Value::String(s) => regex.is_match(s),
+                    other => regex.is_match(&other.to_string()),
+                }
+            }
+            JsonComparison::IsNull => actual.is_null(),
+        };
+        if passed {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected a value that {}, but found {}",
+                self, actual
+            ))
+        }
+    }
+}
+
+/// Reads the actual JSON value and the expected value as numbers, if both are numeric.
+fn numbers(actual: &serde_json::Value, expected: &Value) -> Option<(f64, f64)> {
+    let actual = match actual {
+        serde_json::Value::Number(n) => n.as_f64()?,
+        serde_json::Value::String(s) => s.trim().parse().ok()?,
+        _ => return None,
+    };
+    let expected = match expected {
+        Value::Number(n) => *n as f64,
+        Value::Float(f) => *f,
+        Value::String(s) => s.trim().parse().ok()?,
+        _ => return None,
+    };
+    Some((actual, expected))
+}
+
+impl fmt::Display for JsonComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonComparison::GreaterThan(v) => write!(f, "is greater than {}", v),
+            JsonComparison::LessThan(v) => write!(f, "is less than {}", v),
+            JsonComparison::Between(low, high) => write!(f, "is between {} and {}", low, high),
+            JsonComparison::NotEquals(v) => write!(f, "is not equal to {}", v),
+            JsonComparison::Matches(pattern) => write!(f, "matches '{}'", pattern),
+            JsonComparison::IsNull => write!(f, "is null"),
+        }
+    }
+}
+
 // All possible conditions that can trigger a rule.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Condition {
+    /// A terminal condition checked against a named terminal actor (e.g. `Server`)
+    /// instead of the default `Terminal`.
+    TerminalActor {
+        actor: String,
+        condition: Box<Condition>,
+    },
     // deprecated
     Wait {
         op: String,
@@ -260,13 +414,73 @@ pub enum Condition {
     },
     LastCommandSucceeded,
     LastCommandFailed,
-    LastCommandExitCodeIs(i32),
+    LastCommandExitCodeIs(i64),
+    /// The last `run` was ended by the signal, e.g. `SIGTERM`.
+    LastCommandKilledBy(String),
+    LastCommandTimedOut,
+    LastCommandDurationBelow {
+        duration: f32,
+    },
+    IsRunning,
+    ExitedWith(i64),
+    /// A background process was ended by the signal.
+    KilledBy(String),
     StdoutIsEmpty,
     StderrIsEmpty,
     StderrContains(String),
     OutputStartsWith(String),
     OutputEndsWith(String),
     OutputEquals(String),
+    // --- Stream Conditions (stdout, stderr or both in the order they were written) ---
+    StreamContains {
+        stream: OutputStream,
+        text: String,
+    },
+    StreamMatches {
+        stream: OutputStream,
+        regex: String,
+        capture_as: Option<String>,
+    },
+    /// Compares the trimmed stream.
+    StreamEquals {
+        stream: OutputStream,
+        text: String,
+    },
+    StreamLineCount {
+        stream: OutputStream,
+        count: usize,
+    },
+    /// Compares a 1-based line of the stream, without trailing spaces.
+    StreamLineEquals {
+        stream: OutputStream,
+        line: usize,
+        text: String,
+    },
+    /// Passes if `second` was printed after `first`, on either stream.
+    PrintedBefore {
+        first: String,
+        second: String,
+    },
+    // --- Screen Conditions (interactive terminal) ---
+    ScreenContains(String),
+    /// Compares a 1-based screen row, without trailing spaces.
+    ScreenLineEquals {
+        line: usize,
+        text: String,
+    },
+    /// The 1-based row and column of the cursor.
+    CursorAt {
+        row: u16,
+        col: u16,
+    },
+    /// Compares the screen with the golden file `snapshots/<name>.snap`.
+    ScreenMatchesSnapshot {
+        name: String,
+    },
+    /// Compares the output with the golden file `snapshots/<name>.snap`.
+    OutputMatchesSnapshot {
+        name: String,
+    },
     // --- JSON Conditions ---
     OutputIsValidJson,
     JsonValueIsString {
@@ -300,6 +514,10 @@ pub enum Condition {
         path: String,
         count: i32,
     },
+    JsonOutputAtCompare {
+        path: String,
+        comparison: JsonComparison,
+    },
     // --- Filesystem Conditions ---
     FileExists {
         path: String,
@@ -323,6 +541,10 @@ pub enum Condition {
     FileIsNotEmpty {
         path: String,
     },
+    FileMatchesSnapshot {
+        path: String,
+        name: String,
+    },
     // --- Web Conditions ---
     ResponseStatusIs(u16),
     ResponseStatusIsSuccess,
@@ -338,6 +560,32 @@ pub enum Condition {
         regex: String,
         capture_as: Option<String>,
     },
+    ResponseBodyMatchesSnapshot {
+        name: String,
+    },
+    ResponseHeaderExists {
+        name: String,
+    },
+    ResponseHeaderEquals {
+        name: String,
+        value: String,
+    },
+    ResponseHeaderContains {
+        name: String,
+        value: String,
+    },
+    ResponseHeaderMatches {
+        name: String,
+        regex: String,
+        capture_as: Option<String>,
+    },
+    CookieExists {
+        name: String,
+    },
+    CookieEquals {
+        name: String,
+        value: String,
+    },
     ResponseBodyEqualsJson {
         expected: String,
         ignored: Vec<String>,
@@ -349,6 +597,10 @@ pub enum Condition {
         path: String,
         expected_value: Value,
     },
+    JsonPathCompare {
+        path: String,
+        comparison: JsonComparison,
+    },
     JsonPathCapture {
         path: String,
         capture_as: String,
@@ -369,6 +621,24 @@ pub enum Condition {
     PortIsClosed {
         port: u16,
     },
+    // --- Mock Conditions ---
+    /// Passes if a mock server received a matching request, exactly `times` times if given.
+    MockReceived {
+        method: String,
+        path: String,
+        times: Option<usize>,
+    },
+    MockLastRequestBodyContains {
+        text: String,
+    },
+    MockLastRequestHeaderEquals {
+        name: String,
+        value: String,
+    },
+    MockLastRequestJsonPathEquals {
+        path: String,
+        expected_value: Value,
+    },
 }
 
 // All possible actions that can be executed.
@@ -391,10 +661,57 @@ pub enum Action {
     Run {
         actor: String,
         command: String,
+        /// Overrides the suite's `timeout_seconds` for this command (`within 5s`).
+        timeout: Option<f32>,
+        /// Extra environment variables for this command (`with_env { KEY: "value" }`).
+        env: Vec<(String, String)>,
+        /// What the command reads on stdin. It reads nothing by default.
+        stdin: Option<StdinSource>,
+        /// Stores the command's trimmed stdout in this variable (`as name`).
+        capture_as: Option<String>,
+    },
+    /// Stores the stdout, stderr or exit code of the last `run` in a variable.
+    Capture {
+        actor: String,
+        source: CaptureSource,
+        variable: String,
     },
     SetCwd {
+        actor: String,
         path: String,
     },
+    /// Writes text to the interactive PTY session, as if typed.
+    Type {
+        actor: String,
+        text: String,
+    },
+    /// Sends a named key (e.g. `Enter`, `Ctrl-C`, `Up`) to the interactive PTY session.
+    Press {
+        actor: String,
+        key: String,
+    },
+    /// Starts a program inside a new interactive PTY session.
+    Spawn {
+        actor: String,
+        command: String,
+    },
+    /// Starts a background process whose output is captured under `name`.
+    Start {
+        actor: String,
+        command: String,
+        name: String,
+    },
+    /// Stops a background process started with `Start`.
+    Stop {
+        actor: String,
+        name: String,
+    },
+    /// Sends a signal, e.g. `SIGINT`, to a background process started with `Start`.
+    SendSignal {
+        actor: String,
+        signal: String,
+        name: String,
+    },
     // --- Filesystem Actions ---
     CreateFile {
         path: String,
@@ -409,6 +726,11 @@ pub enum Action {
     DeleteDir {
         path: String,
     },
+    /// Copies a directory tree, e.g. fixtures, from next to the test suite into the working directory.
+    CopyDir {
+        source: String,
+        destination: String,
+    },
     ReadFile {
         path: String,
         variable: Option<String>,
@@ -432,27 +754,176 @@ pub enum Action {
     HttpClearCookies,
     HttpGet {
         url: String,
+        options: HttpOptions,
+        /// Repeats the request until a condition holds (`until ... within 30s`).
+        until: Option<Box<HttpPoll>>,
     },
     HttpPost {
         url: String,
         body: String,
+        options: HttpOptions,
     },
     HttpPut {
         url: String,
         body: String,
+        options: HttpOptions,
     },
     HttpPatch {
         url: String,
         body: String,
+        options: HttpOptions,
     },
     HttpDelete {
         url: String,
+        options: HttpOptions,
+    },
+    // --- Mock Actions ---
+    /// Starts a stub HTTP server on a local port, or replaces the routes of a running one.
+    MockServe {
+        port: u16,
+        routes: Vec<MockRoute>,
     },
+    /// Runs the actions, then repeats them every `every` seconds until the test's `then`
+    /// conditions pass or `within` seconds have passed.
+    Eventually {
+        actions: Vec<Action>,
+        within: f32,
+        every: f32,
+    },
+}
+
+/// Options of a single web request, e.g. `with_query { page: 2 } timeout 2s`. Unset options
+/// fall back to the suite settings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HttpOptions {
+    /// Query parameters added to the URL (`with_query { page: 2 }`).
+    pub query: Vec<(String, String)>,
+    /// Headers for this request only, replacing headers set with `Web set_header`.
+    pub headers: Vec<(String, String)>,
+    /// Overrides the suite's `http_timeout` (`timeout 2s`).
+    pub timeout: Option<f32>,
+    /// Overrides the suite's `http_follow_redirects` (`follow_redirects false`).
+    pub follow_redirects: Option<bool>,
+    /// How many redirects are followed before the response is returned as it is.
+    pub max_redirects: Option<usize>,
+}
+
+/// How `Web http_get ... until <condition> within 30s every 500ms` repeats a request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpPoll {
+    pub condition: Condition,
+    /// Seconds to keep trying.
+    pub within: f32,
+    /// Seconds between requests.
+    pub every: f32,
+}
+
+/// What `Terminal capture ... as name` stores.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureSource {
+    Stdout,
+    Stderr,
+    ExitCode,
+}
+
+impl fmt::Display for CaptureSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureSource::Stdout => write!(f, "stdout"),
+            CaptureSource::Stderr => write!(f, "stderr"),
+            CaptureSource::ExitCode => write!(f, "exit_code"),
+        }
+    }
+}
+
+/// The output stream a stream condition checks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+    /// Stdout and stderr in the order they were written.
+    Interleaved,
+}
+
+impl OutputStream {
+    /// The stream named at the start of a condition keyword, e.g. `stderr_matches`.
+    pub fn from_keyword(keyword: &str) -> Self {
+        if keyword.starts_with("stdout") {
+            OutputStream::Stdout
+        } else if keyword.starts_with("stderr") {
+            OutputStream::Stderr
+        } else {
+            OutputStream::Interleaved
+        }
+    }
+}
+
+impl fmt::Display for OutputStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputStream::Stdout => write!(f, "stdout"),
+            OutputStream::Stderr => write!(f, "stderr"),
+            OutputStream::Interleaved => write!(f, "interleaved_output"),
+        }
+    }
+}
+
+/// A route of a `Mock serve` block, e.g. `GET "/users/*" responds 200 with_body "..."`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRoute {
+    /// The HTTP method, or `ANY`.
+    pub method: String,
+    /// The request path, which may contain `*` wildcards.
+    pub path: String,
+    pub response: MockResponse,
+    /// Seconds to wait before responding.
+    pub delay: f32,
+    /// How many requests the route answers before later routes take over.
+    pub times: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockResponse {
+    Respond {
+        status: u16,
+        body: String,
+        headers: Vec<(String, String)>,
+    },
+    /// Closes the connection without a response, like a crashed server.
+    CloseConnection,
+}
+
+/// The input of a `Terminal run` command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StdinSource {
+    /// `with_stdin "..."`
+    Text(String),
+    /// `with_stdin_file "..."`, relative to the terminal's working directory.
+    File(String),
 }
 
 impl Action {
     pub fn is_filesystem_creation(&self) -> bool {
-        matches!(self, Self::CreateFile { .. } | Self::CreateDir { .. })
+        matches!(
+            self,
+            Self::CreateFile { .. } | Self::CreateDir { .. } | Self::CopyDir { .. }
+        )
+    }
+
+    /// The terminal actor that runs this action, if it is a terminal action.
+    pub fn terminal_actor(&self) -> Option<&str> {
+        match self {
+            Self::Run { actor, .. }
+            | Self::SetCwd { actor, .. }
+            | Self::Type { actor, .. }
+            | Self::Press { actor, .. }
+            | Self::Spawn { actor, .. }
+            | Self::Start { actor, .. }
+            | Self::Stop { actor, .. }
+            | Self::SendSignal { actor, .. }
+            | Self::Capture { actor, .. } => Some(actor),
+            _ => None,
+        }
     }
 }
 
@@ -460,10 +931,12 @@ impl Action {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     String(String),
-    Number(i32),
+    Number(i64),
+    Float(f64),
     Bool(bool),
     Array(Vec<Value>),
     Object(HashMap<String, Value>),
+    Null,
 }
 
 impl Value {
@@ -471,7 +944,9 @@ impl Value {
         match self {
             Value::String(s) => s.clone(),
             Value::Number(n) => n.to_string(),
+            Value::Float(f) => f.to_string(),
             Value::Bool(b) => b.to_string(),
+            Value::Null => "null".to_string(),
             Value::Array(a) => a
                 .iter()
                 .map(Value::as_string)
@@ -491,7 +966,10 @@ impl Value {
         match self {
             Value::String(s) => serde_json::Value::String(s.clone()),
             Value::Number(n) => serde_json::Value::Number(serde_json::Number::from(*n)),
+            Value::Float(f) => serde_json::Number::from_f64(*f)
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
             Value::Bool(b) => serde_json::Value::Bool(*b),
+            Value::Null => serde_json::Value::Null,
             Value::Array(arr) => {
                 let vec = arr.iter().map(|v| v.to_json_value()).collect();
                 serde_json::Value::Array(vec)
@@ -505,6 +983,35 @@ impl Value {
             }
         }
     }
+
+    /// Compares this value with a JSON value. Numbers are compared numerically, so `1`,
+    /// `1.0` and `"1"` (e.g. from a substituted variable) all match the JSON number `1`.
+    pub fn matches_json(&self, json: &serde_json::Value) -> bool {
+        json_values_equal(&self.to_json_value(), json)
+    }
+}
+
+fn json_values_equal(expected: &serde_json::Value, actual: &serde_json::Value) -> bool {
+    use serde_json::Value as Json;
+    match (expected, actual) {
+        (Json::Number(a), Json::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => a.as_f64() == b.as_f64(),
+        },
+        (Json::String(s), Json::Number(n)) => match (s.parse::<i64>(), n.as_i64()) {
+            (Ok(a), Some(b)) => a == b,
+            _ => s.parse::<f64>().ok() == n.as_f64(),
+        },
+        (Json::Array(a), Json::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| json_values_equal(x, y))
+        }
+        (Json::Object(a), Json::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, v)| b.get(k).is_some_and(|other| json_values_equal(v, other)))
+        }
+        _ => expected == actual,
+    }
 }
 
 impl fmt::Display for Value {
@@ -512,7 +1019,9 @@ impl fmt::Display for Value {
         match self {
             Value::String(s) => write!(f, "{}", s),
             Value::Number(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
             Value::Bool(b) => write!(f, "{}", b),
+            Value::Null => write!(f, "null"),
             Value::Array(arr) => {
                 let items: Vec<String> = arr.iter().map(|v| v.to_string()).collect();
                 write!(f, "[{}]", items.join(", "))
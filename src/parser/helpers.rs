@@ -1,9 +1,8 @@
-use crate::backend::filesystem_backend::FileSystemBackend;
-use crate::backend::system_backend::SystemBackend;
-use crate::backend::terminal_backend::TerminalBackend;
-use crate::backend::web_backend::WebBackend;
+use crate::backend::backends::Backends;
+use crate::backend::process::signal_number;
 use crate::parser::ast::{
-    Action, Condition, GivenStep, StateCondition, TaskArg, TaskCall, TestCase, TestState, ThenStep,
+    Action, Condition, GivenStep, HttpOptions, HttpPoll, JsonComparison, MockResponse, MockRoute,
+    OutputStream, StateCondition, StdinSource, TaskArg, TaskCall, TestCase, TestState, ThenStep,
     Value, WhenStep,
 };
 use jsonpath_lib::selector;
@@ -20,10 +19,7 @@ pub fn check_all_conditions_met(
     current_wait: f32,
     env_vars: &mut HashMap<String, String>,
     last_exit_code: &Option<i32>,
-    fs_backend: &FileSystemBackend,
-    terminal_backend: &mut TerminalBackend,
-    web_backend: &WebBackend,
-    system_backend: &SystemBackend,
+    backends: &mut Backends,
     verbose: bool,
 ) -> bool {
     conditions.iter().all(|condition| {
@@ -36,10 +32,7 @@ pub fn check_all_conditions_met(
             current_wait,
             env_vars,
             last_exit_code,
-            fs_backend,
-            terminal_backend,
-            web_backend,
-            system_backend,
+            backends,
             verbose,
         );
         // if verbose {
@@ -72,10 +65,7 @@ pub fn check_condition(
     current_wait: f32,
     env_vars: &mut HashMap<String, String>,
     last_exit_code: &Option<i32>,
-    fs_backend: &FileSystemBackend,
-    terminal_backend: &mut TerminalBackend,
-    web_backend: &WebBackend,
-    system_backend: &SystemBackend,
+    backends: &mut Backends,
     verbose: bool,
 ) -> bool {
     let cleaned_buffer = strip(output_buffer);
@@ -83,13 +73,61 @@ pub fn check_condition(
 
     // For synchronous commands, the output is in `last_stdout`.
     // For asynchronous commands, it's in the PTY `output_buffer`.
-    let content_to_check = if !terminal_backend.last_stdout.is_empty() {
-        terminal_backend.last_stdout.as_str()
+    let content_to_check = if !backends.terminal.last_stdout.is_empty() {
+        backends.terminal.last_stdout.as_str()
     } else {
         buffer.as_ref()
     };
 
     match condition {
+        Condition::TerminalActor { actor, condition } => {
+            // A name given to a background process refers to that process.
+            if backends.terminal.has_process(actor) {
+                return check_process_condition(
+                    actor,
+                    condition,
+                    test_states,
+                    current_wait,
+                    env_vars,
+                    backends,
+                    verbose,
+                );
+            }
+            if let Some(owner) = backends.sessions.process_owner(actor) {
+                let mut session = backends.sessions.take(&owner);
+                let passed = backends.with_terminal(&mut session.backend, |backends| {
+                    check_process_condition(
+                        actor,
+                        condition,
+                        test_states,
+                        current_wait,
+                        env_vars,
+                        backends,
+                        verbose,
+                    )
+                });
+                backends.sessions.put_back(&owner, session);
+                return passed;
+            }
+            let mut session = backends.sessions.take(actor);
+            session.read_output();
+            let stderr = session.backend.last_stderr.clone();
+            let passed = backends.with_terminal(&mut session.backend, |backends| {
+                check_condition(
+                    condition,
+                    test_states,
+                    &session.output_buffer,
+                    &stderr,
+                    current_wait,
+                    env_vars,
+                    &session.last_exit_code,
+                    backends,
+                    verbose,
+                )
+            });
+            backends.sessions.put_back(actor, session);
+            passed
+        }
         Condition::Wait { op, wait } => match op.as_str() {
             ">=" => current_wait >= *wait,
             "<=" => current_wait <= *wait,
@@ -129,7 +167,7 @@ pub fn check_condition(
                             }
                             env_vars.insert(var_name.clone(), value);
                             // Clear last_stdout after successful capture to prevent reuse
-                            terminal_backend.last_stdout.clear();
+                            backends.terminal.last_stdout.clear();
                         }
                     }
                     return true;
@@ -148,21 +186,46 @@ pub fn check_condition(
             *last_exit_code == Some(0)
         }
         Condition::LastCommandFailed => last_exit_code.is_some_and(|code| code != 0),
-        Condition::LastCommandExitCodeIs(expected_code) => *last_exit_code == Some(*expected_code),
-        Condition::FileExists { path } => fs_backend.file_exists(
+        Condition::LastCommandExitCodeIs(expected_code) => {
+            last_exit_code.is_some_and(|code| i64::from(code) == *expected_code)
+        }
+        Condition::LastCommandKilledBy(signal) => backends
+            .terminal
+            .last_run
+            .as_ref()
+            .and_then(|run| run.signal)
+            .is_some_and(|number| signal_number(signal) == Some(number)),
+        Condition::LastCommandTimedOut => backends
+            .terminal
+            .last_run
+            .as_ref()
+            .is_some_and(|run| run.timed_out),
+        Condition::LastCommandDurationBelow { duration } => backends
+            .terminal
+            .last_run
+            .as_ref()
+            .is_some_and(|run| run.duration.as_secs_f32() < *duration),
+        // Only background processes, which are checked by name, report their signal.
+        Condition::KilledBy(_) => false,
+        Condition::IsRunning => backends.terminal.is_running(),
+        Condition::ExitedWith(expected_code) => backends
+            .terminal
+            .exit_code()
+            .is_some_and(|code| i64::from(code) == *expected_code),
+        Condition::FileExists { path } => backends.fs.file_exists(
             &substitute_string(path, env_vars),
-            terminal_backend.get_cwd(),
+            backends.terminal.get_cwd(),
             verbose,
         ),
-        Condition::FileDoesNotExist { path } => fs_backend.file_does_not_exist(
+        Condition::FileDoesNotExist { path } => backends.fs.file_does_not_exist(
             &substitute_string(path, env_vars),
-            terminal_backend.get_cwd(),
+            backends.terminal.get_cwd(),
             verbose,
         ),
         Condition::FileIsEmpty { path } => {
-            let resolved_path = fs_backend.resolve_path(
+            let resolved_path = backends.fs.resolve_path(
                 &substitute_string(path, env_vars),
-                terminal_backend.get_cwd(),
+                backends.terminal.get_cwd(),
             );
             if verbose {
                 println!("Checking if file is empty: {:?}", resolved_path);
@@ -173,9 +236,9 @@ pub fn check_condition(
                     .unwrap_or(false)
         }
         Condition::FileIsNotEmpty { path } => {
-            let resolved_path = fs_backend.resolve_path(
+            let resolved_path = backends.fs.resolve_path(
                 &substitute_string(path, env_vars),
-                terminal_backend.get_cwd(),
+                backends.terminal.get_cwd(),
             );
             if verbose {
                 println!("Checking if file is not empty: {:?}", resolved_path);
@@ -185,20 +248,32 @@ pub fn check_condition(
                     .map(|m| m.len() > 0)
                     .unwrap_or(false)
         }
-        Condition::DirExists { path } => fs_backend.dir_exists(
+        Condition::FileMatchesSnapshot { path, name } => {
+            let resolved_path = backends.fs.resolve_path(path, backends.terminal.get_cwd());
+            std::fs::read(resolved_path).is_ok_and(|content| {
+                let content = String::from_utf8_lossy(&content);
+                backends.fs.snapshots.check(name, &content).is_ok()
+            })
+        }
+        Condition::ResponseBodyMatchesSnapshot { name } => backends
+            .web
+            .last_response
+            .as_ref()
+            .is_some_and(|response| backends.fs.snapshots.check(name, &response.body).is_ok()),
+        Condition::DirExists { path } => backends.fs.dir_exists(
             &substitute_string(path, env_vars),
-            terminal_backend.get_cwd(),
+            backends.terminal.get_cwd(),
             verbose,
         ),
-        Condition::DirDoesNotExist { path } => fs_backend.dir_does_not_exist(
+        Condition::DirDoesNotExist { path } => backends.fs.dir_does_not_exist(
             &substitute_string(path, env_vars),
-            terminal_backend.get_cwd(),
+            backends.terminal.get_cwd(),
             verbose,
         ),
-        Condition::FileContains { path, content } => fs_backend.file_contains(
+        Condition::FileContains { path, content } => backends.fs.file_contains(
             &substitute_string(path, env_vars),
             &substitute_string(content, env_vars),
-            terminal_backend.get_cwd(),
+            backends.terminal.get_cwd(),
             verbose,
         ),
         Condition::StdoutIsEmpty => content_to_check.trim().is_empty(),
@@ -209,9 +284,48 @@ pub fn check_condition(
             stderr_buffer.trim().is_empty()
         }
         Condition::StderrContains(text) => stderr_buffer.contains(text),
+        Condition::StreamContains { .. }
+        | Condition::StreamMatches { .. }
+        | Condition::StreamEquals { .. }
+        | Condition::StreamLineCount { .. }
+        | Condition::StreamLineEquals { .. }
+        | Condition::PrintedBefore { .. } => check_stream_condition(
+            condition,
+            &backends.terminal.run_stdout,
+            stderr_buffer,
+            &backends.terminal.run_output,
+            env_vars,
+            verbose,
+        ),
         Condition::OutputStartsWith(text) => content_to_check.trim().starts_with(text),
         Condition::OutputEndsWith(text) => content_to_check.trim().ends_with(text),
         Condition::OutputEquals(text) => content_to_check.trim() == text.trim(),
+        Condition::ScreenContains(text) => backends
+            .terminal
+            .screen_lines()
+            .iter()
+            .any(|line| line.contains(text.as_str())),
+        Condition::ScreenLineEquals { line, text } => backends
+            .terminal
+            .screen_lines()
+            .get(line - 1)
+            .map_or(text.is_empty(), |actual| actual == text.trim_end()),
+        Condition::CursorAt { row, col } => {
+            backends.terminal.cursor_position() == Some((*row, *col))
+        }
+        Condition::ScreenMatchesSnapshot { name } => {
+            let screen = backends.terminal.screen_lines();
+            // Nothing has been drawn yet, so there is nothing to compare or record.
+            !screen.is_empty()
+                && backends
+                    .fs
+                    .snapshots
+                    .check(name, &screen.join("\n"))
+                    .is_ok()
+        }
+        Condition::OutputMatchesSnapshot { name } => {
+            backends.fs.snapshots.check(name, content_to_check).is_ok()
+        }
         Condition::OutputIsValidJson => {
             serde_json::from_str::<serde_json::Value>(content_to_check.trim()).is_ok()
         }
@@ -232,6 +346,27 @@ pub fn check_condition(
                 Err(_) => false,
             }
         }
+        Condition::JsonOutputAtEquals { path, value } => {
+            json_output_at(content_to_check, path).is_some_and(|actual| value.matches_json(&actual))
+        }
+        Condition::JsonOutputAtIncludes { path, value } => {
+            match json_output_at(content_to_check, path) {
+                Some(serde_json::Value::Array(items)) => {
+                    items.iter().any(|item| value.matches_json(item))
+                }
+                Some(serde_json::Value::String(s)) => s.contains(&value.as_string()),
+                _ => false,
+            }
+        }
+        Condition::JsonOutputAtCompare { path, comparison } => comparison
+            .evaluate(json_output_at(content_to_check, path).as_ref())
+            .is_ok(),
+        Condition::JsonOutputAtHasItemCount { path, count } => {
+            match json_output_at(content_to_check, path) {
+                Some(serde_json::Value::Array(items)) => items.len() as i64 == i64::from(*count),
+                _ => false,
+            }
+        }
         Condition::ResponseStatusIs(_)
         | Condition::ResponseStatusIsSuccess
         | Condition::ResponseStatusIsError
@@ -239,6 +374,12 @@ pub fn check_condition(
         | Condition::ResponseTimeIsBelow { .. }
         | Condition::ResponseBodyContains { .. }
         | Condition::ResponseBodyMatches { .. }
+        | Condition::ResponseHeaderExists { .. }
+        | Condition::ResponseHeaderEquals { .. }
+        | Condition::ResponseHeaderContains { .. }
+        | Condition::ResponseHeaderMatches { .. }
+        | Condition::CookieExists { .. }
+        | Condition::CookieEquals { .. }
         | Condition::ResponseBodyEqualsJson { .. }
         | Condition::JsonValueIsString { .. }
         | Condition::JsonValueIsNumber { .. }
@@ -248,30 +389,148 @@ pub fn check_condition(
         | Condition::JsonBodyHasPath { .. }
         | Condition::JsonPathCapture { .. }
         | Condition::JsonPathEquals { .. } => {
-            web_backend.check_condition(condition, env_vars, verbose)
+            backends.web.check_condition(condition, env_vars, verbose)
         }
         // --- System Conditions ---
         Condition::ServiceIsRunning { name } => {
-            system_backend.check_service_is_running(name, verbose)
+            backends.system.check_service_is_running(name, verbose)
         }
         Condition::ServiceIsStopped { name } => {
-            system_backend.check_service_is_stopped(name, verbose)
+            backends.system.check_service_is_stopped(name, verbose)
         }
         Condition::ServiceIsInstalled { name } => {
-            system_backend.check_service_is_installed(name, verbose)
+            backends.system.check_service_is_installed(name, verbose)
         }
         Condition::PortIsListening { port } => {
-            system_backend.check_port_is_listening(*port, verbose)
+            backends.system.check_port_is_listening(*port, verbose)
+        }
+        Condition::PortIsClosed { port } => backends.system.check_port_is_closed(*port, verbose),
+        // --- Mock Conditions ---
+        Condition::MockReceived { .. }
+        | Condition::MockLastRequestBodyContains { .. }
+        | Condition::MockLastRequestHeaderEquals { .. }
+        | Condition::MockLastRequestJsonPathEquals { .. } => {
+            backends.mock.check_condition(condition, verbose)
         }
-        Condition::PortIsClosed { port } => system_backend.check_port_is_closed(*port, verbose),
         _ => false, // Other conditions not implemented yet
     }
 }
 
+/// Checks a condition against the background process started as `name` on the main terminal of `backends`.
+fn check_process_condition(
+    name: &str,
+    condition: &Condition,
+    test_states: &HashMap<String, TestState>,
+    current_wait: f32,
+    env_vars: &mut HashMap<String, String>,
+    backends: &mut Backends,
+    verbose: bool,
+) -> bool {
+    let process = backends.terminal.process_mut(name).unwrap();
+    match condition {
+        Condition::IsRunning => process.is_running(),
+        Condition::ExitedWith(expected_code) => process
+            .exit_code()
+            .is_some_and(|code| i64::from(code) == *expected_code),
+        Condition::KilledBy(signal) => process
+            .signal()
+            .is_some_and(|number| signal_number(signal) == Some(number)),
+        Condition::StreamContains { .. }
+        | Condition::StreamMatches { .. }
+        | Condition::StreamEquals { .. }
+        | Condition::StreamLineCount { .. }
+        | Condition::StreamLineEquals { .. }
+        | Condition::PrintedBefore { .. } => {
+            let (stdout, stderr, output) = (process.stdout(), process.stderr(), process.output());
+            check_stream_condition(condition, &stdout, &stderr, &output, env_vars, verbose)
+        }
+        _ => {
+            let output = process.output();
+            let stderr = process.stderr();
+            let exit_code = process.exit_code();
+            // Output conditions read `last_stdout` first, so hide the last `run` meanwhile.
+            let last_stdout = std::mem::take(&mut backends.terminal.last_stdout);
+            let passed = check_condition(
+                condition,
+                test_states,
+                &output,
+                &stderr,
+                current_wait,
+                env_vars,
+                &exit_code,
+                backends,
+                verbose,
+            );
+            backends.terminal.last_stdout = last_stdout;
+            passed
+        }
+    }
+}
+
+/// Checks a condition on the stdout or stderr of a command, or on both in the order they
+/// were written (`interleaved`).
+fn check_stream_condition(
+    condition: &Condition,
+    stdout: &str,
+    stderr: &str,
+    interleaved: &str,
+    env_vars: &mut HashMap<String, String>,
+    verbose: bool,
+) -> bool {
+    let text_of = |stream: &OutputStream| match stream {
+        OutputStream::Stdout => stdout,
+        OutputStream::Stderr => stderr,
+        OutputStream::Interleaved => interleaved,
+    };
+    match condition {
+        Condition::StreamContains { stream, text } => text_of(stream).contains(text.as_str()),
+        Condition::StreamMatches {
+            stream,
+            regex,
+            capture_as,
+        } => {
+            let Some(captures) = regex::Regex::new(regex)
+                .ok()
+                .and_then(|re| re.captures(text_of(stream)))
+            else {
+                return false;
+            };
+            if let (Some(var_name), Some(group)) = (capture_as, captures.get(1)) {
+                if verbose {
+                    println!(
+                        "  [DEBUG] Captured value '{}' into variable '{}'",
+                        group.as_str(),
+                        var_name
+                    );
+                }
+                env_vars.insert(var_name.clone(), group.as_str().to_string());
+            }
+            true
+        }
+        Condition::StreamEquals { stream, text } => text_of(stream).trim() == text.trim(),
+        Condition::StreamLineCount { stream, count } => text_of(stream).lines().count() == *count,
+        Condition::StreamLineEquals { stream, line, text } => text_of(stream)
+            .lines()
+            .nth(line - 1)
+            .is_some_and(|actual| actual.trim_end() == text.trim_end()),
+        Condition::PrintedBefore { first, second } => interleaved
+            .find(first.as_str())
+            .is_some_and(|at| interleaved[at + first.len()..].contains(second.as_str())),
+        _ => false,
+    }
+}
+
 /// Creates a new Action with its string values substituted from the state map.
 pub fn _substitute_variables(action: &Action, state: &HashMap<String, String>) -> Action {
     match action {
-        Action::Run { actor, command } => {
+        Action::Run {
+            actor,
+            command,
+            timeout,
+            env,
+            stdin,
+            capture_as,
+        } => {
             println!(
                 "  [DEBUG] Substituting in Run action: command='{}'",
                 command
@@ -279,6 +538,10 @@ pub fn _substitute_variables(action: &Action, state: &HashMap<String, String>) -
             Action::Run {
                 actor: actor.clone(),
                 command: substitute_string(command, state),
+                timeout: *timeout,
+                env: env.clone(),
+                stdin: stdin.clone(),
+                capture_as: capture_as.clone(),
             }
         }
         Action::CreateFile { path, content } => Action::CreateFile {
@@ -294,6 +557,13 @@ pub fn _substitute_variables(action: &Action, state: &HashMap<String, String>) -
         Action::DeleteDir { path } => Action::DeleteDir {
             path: substitute_string(path, state),
         },
+        Action::CopyDir {
+            source,
+            destination,
+        } => Action::CopyDir {
+            source: substitute_string(source, state),
+            destination: substitute_string(destination, state),
+        },
         Action::ReadFile { path, variable } => Action::ReadFile {
             path: substitute_string(path, state),
             variable: variable.clone(),
@@ -467,6 +737,10 @@ pub fn substitute_variables_in_condition(
     state: &HashMap<String, String>,
 ) -> Condition {
     match condition {
+        Condition::TerminalActor { actor, condition } => Condition::TerminalActor {
+            actor: actor.clone(),
+            condition: Box::new(substitute_variables_in_condition(condition, state)),
+        },
         Condition::OutputContains { actor, text } => Condition::OutputContains {
             actor: actor.clone(),
             text: substitute_string(text, state),
@@ -507,6 +781,52 @@ pub fn substitute_variables_in_condition(
             Condition::OutputEndsWith(substitute_string(text, state))
         }
         Condition::OutputEquals(text) => Condition::OutputEquals(substitute_string(text, state)),
+        Condition::ScreenContains(text) => {
+            Condition::ScreenContains(substitute_string(text, state))
+        }
+        Condition::ScreenLineEquals { line, text } => Condition::ScreenLineEquals {
+            line: *line,
+            text: substitute_string(text, state),
+        },
+        Condition::ScreenMatchesSnapshot { name } => Condition::ScreenMatchesSnapshot {
+            name: substitute_string(name, state),
+        },
+        Condition::StreamContains { stream, text } => Condition::StreamContains {
+            stream: *stream,
+            text: substitute_string(text, state),
+        },
+        Condition::StreamMatches {
+            stream,
+            regex,
+            capture_as,
+        } => Condition::StreamMatches {
+            stream: *stream,
+            regex: substitute_string(regex, state),
+            capture_as: capture_as.clone(),
+        },
+        Condition::StreamEquals { stream, text } => Condition::StreamEquals {
+            stream: *stream,
+            text: substitute_string(text, state),
+        },
+        Condition::StreamLineEquals { stream, line, text } => Condition::StreamLineEquals {
+            stream: *stream,
+            line: *line,
+            text: substitute_string(text, state),
+        },
+        Condition::PrintedBefore { first, second } => Condition::PrintedBefore {
+            first: substitute_string(first, state),
+            second: substitute_string(second, state),
+        },
+        Condition::OutputMatchesSnapshot { name } => Condition::OutputMatchesSnapshot {
+            name: substitute_string(name, state),
+        },
+        Condition::FileMatchesSnapshot { path, name } => Condition::FileMatchesSnapshot {
+            path: substitute_string(path, state),
+            name: substitute_string(name, state),
+        },
+        Condition::ResponseBodyMatchesSnapshot { name } => Condition::ResponseBodyMatchesSnapshot {
+            name: substitute_string(name, state),
+        },
         Condition::ResponseBodyContains { value } => Condition::ResponseBodyContains {
             value: substitute_string(value, state),
         },
@@ -514,6 +834,33 @@ pub fn substitute_variables_in_condition(
             regex: substitute_string(regex, state),
             capture_as: capture_as.clone(),
         },
+        Condition::ResponseHeaderExists { name } => Condition::ResponseHeaderExists {
+            name: substitute_string(name, state),
+        },
+        Condition::ResponseHeaderEquals { name, value } => Condition::ResponseHeaderEquals {
+            name: substitute_string(name, state),
+            value: substitute_string(value, state),
+        },
+        Condition::ResponseHeaderContains { name, value } => Condition::ResponseHeaderContains {
+            name: substitute_string(name, state),
+            value: substitute_string(value, state),
+        },
+        Condition::ResponseHeaderMatches {
+            name,
+            regex,
+            capture_as,
+        } => Condition::ResponseHeaderMatches {
+            name: substitute_string(name, state),
+            regex: substitute_string(regex, state),
+            capture_as: capture_as.clone(),
+        },
+        Condition::CookieExists { name } => Condition::CookieExists {
+            name: substitute_string(name, state),
+        },
+        Condition::CookieEquals { name, value } => Condition::CookieEquals {
+            name: substitute_string(name, state),
+            value: substitute_string(value, state),
+        },
         Condition::ResponseBodyEqualsJson { expected, ignored } => {
             Condition::ResponseBodyEqualsJson {
                 expected: substitute_string(expected, state),
@@ -554,6 +901,28 @@ pub fn substitute_variables_in_condition(
             path: substitute_string(path, state),
             size: *size,
         },
+        Condition::JsonOutputAtEquals { path, value } => Condition::JsonOutputAtEquals {
+            path: substitute_string(path, state),
+            value: substitute_value(value, state),
+        },
+        Condition::JsonOutputAtIncludes { path, value } => Condition::JsonOutputAtIncludes {
+            path: substitute_string(path, state),
+            value: substitute_value(value, state),
+        },
+        Condition::JsonOutputAtCompare { path, comparison } => Condition::JsonOutputAtCompare {
+            path: substitute_string(path, state),
+            comparison: substitute_comparison(comparison, state),
+        },
+        Condition::JsonPathCompare { path, comparison } => Condition::JsonPathCompare {
+            path: substitute_string(path, state),
+            comparison: substitute_comparison(comparison, state),
+        },
+        Condition::JsonOutputAtHasItemCount { path, count } => {
+            Condition::JsonOutputAtHasItemCount {
+                path: substitute_string(path, state),
+                count: *count,
+            }
+        }
         // --- System Conditions ---
         Condition::ServiceIsRunning { name } => Condition::ServiceIsRunning {
             name: substitute_string(name, state),
@@ -566,6 +935,32 @@ pub fn substitute_variables_in_condition(
         },
         Condition::PortIsListening { port } => Condition::PortIsListening { port: *port },
         Condition::PortIsClosed { port } => Condition::PortIsClosed { port: *port },
+        // --- Mock Conditions ---
+        Condition::MockReceived {
+            method,
+            path,
+            times,
+        } => Condition::MockReceived {
+            method: method.clone(),
+            path: substitute_string(path, state),
+            times: *times,
+        },
+        Condition::MockLastRequestBodyContains { text } => Condition::MockLastRequestBodyContains {
+            text: substitute_string(text, state),
+        },
+        Condition::MockLastRequestHeaderEquals { name, value } => {
+            Condition::MockLastRequestHeaderEquals {
+                name: substitute_string(name, state),
+                value: substitute_string(value, state),
+            }
+        }
+        Condition::MockLastRequestJsonPathEquals {
+            path,
+            expected_value,
+        } => Condition::MockLastRequestJsonPathEquals {
+            path: substitute_string(path, state),
+            expected_value: substitute_value(expected_value, state),
+        },
         // fallback
         _ => condition.clone(),
     }
@@ -574,13 +969,52 @@ pub fn substitute_variables_in_condition(
 /// Creates a new Action with its string values substituted from the state map.
 pub fn substitute_variables_in_action(action: &Action, state: &HashMap<String, String>) -> Action {
     match action {
-        Action::Run { command, actor } => Action::Run {
+        Action::Run {
+            command,
+            actor,
+            timeout,
+            env,
+            stdin,
+            capture_as,
+        } => Action::Run {
             actor: actor.clone(),
             command: substitute_string(command, state),
+            timeout: *timeout,
+            env: env
+                .iter()
+                .map(|(key, value)| (key.clone(), substitute_string(value, state)))
+                .collect(),
+            stdin: stdin.as_ref().map(|source| match source {
+                StdinSource::Text(text) => StdinSource::Text(substitute_string(text, state)),
+                StdinSource::File(path) => StdinSource::File(substitute_string(path, state)),
+            }),
+            capture_as: capture_as.clone(),
         },
-        Action::SetCwd { path } => Action::SetCwd {
+        Action::SetCwd { actor, path } => Action::SetCwd {
+            actor: actor.clone(),
             path: substitute_string(path, state),
         },
+        Action::Type { actor, text } => Action::Type {
+            actor: actor.clone(),
+            text: substitute_string(text, state),
+        },
+        Action::Press { actor, key } => Action::Press {
+            actor: actor.clone(),
+            key: key.clone(),
+        },
+        Action::Spawn { actor, command } => Action::Spawn {
+            actor: actor.clone(),
+            command: substitute_string(command, state),
+        },
+        Action::Start {
+            actor,
+            command,
+            name,
+        } => Action::Start {
+            actor: actor.clone(),
+            command: substitute_string(command, state),
+            name: name.clone(),
+        },
         Action::Log { message } => Action::Log {
             message: substitute_string(message, state),
         },
@@ -597,27 +1031,68 @@ pub fn substitute_variables_in_action(action: &Action, state: &HashMap<String, S
         Action::DeleteDir { path } => Action::DeleteDir {
             path: substitute_string(path, state),
         },
+        Action::CopyDir {
+            source,
+            destination,
+        } => Action::CopyDir {
+            source: substitute_string(source, state),
+            destination: substitute_string(destination, state),
+        },
         Action::ReadFile { path, variable } => Action::ReadFile {
             path: substitute_string(path, state),
             variable: variable.clone(),
         },
-        Action::HttpGet { url } => Action::HttpGet {
+        Action::HttpGet {
+            url,
+            options,
+            until,
+        } => Action::HttpGet {
             url: substitute_string(url, state),
+            options: substitute_http_options(options, state),
+            until: until.as_ref().map(|poll| {
+                Box::new(HttpPoll {
+                    condition: substitute_variables_in_condition(&poll.condition, state),
+                    ..(**poll).clone()
+                })
+            }),
         },
-        Action::HttpPost { url, body } => Action::HttpPost {
+        Action::HttpPost { url, body, options } => Action::HttpPost {
             url: substitute_string(url, state),
             body: substitute_string(body, state),
+            options: substitute_http_options(options, state),
         },
-        Action::HttpPut { url, body } => Action::HttpPut {
+        Action::HttpPut { url, body, options } => Action::HttpPut {
             url: substitute_string(url, state),
             body: substitute_string(body, state),
+            options: substitute_http_options(options, state),
         },
-        Action::HttpPatch { url, body } => Action::HttpPatch {
+        Action::HttpPatch { url, body, options } => Action::HttpPatch {
             url: substitute_string(url, state),
             body: substitute_string(body, state),
+            options: substitute_http_options(options, state),
         },
-        Action::HttpDelete { url } => Action::HttpDelete {
+        Action::HttpDelete { url, options } => Action::HttpDelete {
             url: substitute_string(url, state),
+            options: substitute_http_options(options, state),
+        },
+        Action::MockServe { port, routes } => Action::MockServe {
+            port: *port,
+            routes: routes
+                .iter()
+                .map(|route| substitute_mock_route(route, state))
+                .collect(),
+        },
+        Action::Eventually {
+            actions,
+            within,
+            every,
+        } => Action::Eventually {
+            actions: actions
+                .iter()
+                .map(|action| substitute_variables_in_action(action, state))
+                .collect(),
+            within: *within,
+            every: *every,
         },
         Action::HttpSetHeader { key, value } => Action::HttpSetHeader {
             key: substitute_string(key, state),
@@ -727,11 +1202,155 @@ pub fn substitute_variables_in_test_case(
             .iter()
             .map(|step| substitute_variables_in_then_step(step, state))
             .collect(),
+        tags: test_case.tags.clone(),
         span: test_case.span.clone(),
         testcase_spans: test_case.testcase_spans.clone(),
     }
 }
 
+fn substitute_comparison(
+    comparison: &JsonComparison,
+    state: &HashMap<String, String>,
+) -> JsonComparison {
+    match comparison {
+        JsonComparison::GreaterThan(v) => JsonComparison::GreaterThan(substitute_value(v, state)),
+        JsonComparison::LessThan(v) => JsonComparison::LessThan(substitute_value(v, state)),
+        JsonComparison::Between(low, high) => {
+            JsonComparison::Between(substitute_value(low, state), substitute_value(high, state))
+        }
+        JsonComparison::NotEquals(v) => JsonComparison::NotEquals(substitute_value(v, state)),
+        JsonComparison::Matches(pattern) => {
+            JsonComparison::Matches(substitute_string(pattern, state))
+        }
+        JsonComparison::IsNull => JsonComparison::IsNull,
+    }
+}
+
+/// Describes why the JSON comparisons and snapshots among `conditions` fail, showing the
+/// actual values or a diff. Used to give failed tests a more useful message than "conditions not met".
+pub fn describe_condition_failures(
+    conditions: &[Condition],
+    env_vars: &HashMap<String, String>,
+    output_buffer: &str,
+    backends: &Backends,
+) -> Vec<String> {
+    let cleaned_buffer = strip(output_buffer);
+    let buffer = String::from_utf8_lossy(&cleaned_buffer);
+    let terminal_output = if !backends.terminal.last_stdout.is_empty() {
+        backends.terminal.last_stdout.as_str()
+    } else {
+        buffer.as_ref()
+    };
+
+    conditions
+        .iter()
+        .filter_map(
+            |condition| match substitute_variables_in_condition(condition, env_vars) {
+                Condition::JsonOutputAtCompare { path, comparison } => comparison
+                    .evaluate(json_output_at(terminal_output, &path).as_ref())
+                    .err()
+                    .map(|e| format!("json_output at '{}': {}", path, e)),
+                Condition::JsonPathCompare { path, comparison } => comparison
+                    .evaluate(backends.web.json_body_at(&path).as_ref())
+                    .err()
+                    .map(|e| format!("json_path at '{}': {}", path, e)),
+                Condition::OutputMatchesSnapshot { name } => backends
+                    .fs
+                    .snapshots
+                    .describe_mismatch(&name, terminal_output),
+                Condition::FileMatchesSnapshot { path, name } => {
+                    let resolved_path =
+                        backends.fs.resolve_path(&path, backends.terminal.get_cwd());
+                    let content = std::fs::read(resolved_path).ok()?;
+                    backends
+                        .fs
+                        .snapshots
+                        .describe_mismatch(&name, &String::from_utf8_lossy(&content))
+                }
+                Condition::ResponseBodyMatchesSnapshot { name } => {
+                    let response = backends.web.last_response.as_ref()?;
+                    backends
+                        .fs
+                        .snapshots
+                        .describe_mismatch(&name, &response.body)
+                }
+                Condition::ScreenMatchesSnapshot { name } => backends
+                    .fs
+                    .snapshots
+                    .describe_mismatch(&name, &backends.terminal.screen_lines().join("\n")),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+/// The names of the snapshots that `conditions` compare against, with variables substituted.
+pub fn snapshot_names(conditions: &[Condition], env_vars: &HashMap<String, String>) -> Vec<String> {
+    conditions
+        .iter()
+        .filter_map(
+            |condition| match substitute_variables_in_condition(condition, env_vars) {
+                Condition::OutputMatchesSnapshot { name }
+                | Condition::ScreenMatchesSnapshot { name }
+                | Condition::FileMatchesSnapshot { name, .. }
+                | Condition::ResponseBodyMatchesSnapshot { name } => Some(name),
+                _ => None,
+            },
+        )
+        .collect()
+}
+
+/// Returns the value at `path` in the JSON output. Paths starting with `/` are JSON
+/// Pointers (as used by `Web json_path`), anything else is a JSONPath expression.
+fn json_output_at(output: &str, path: &str) -> Option<serde_json::Value> {
+    let json_obj = serde_json::from_str::<serde_json::Value>(output.trim()).ok()?;
+    if path.starts_with('/') {
+        return json_obj.pointer(path).cloned();
+    }
+    let mut selector = selector(&json_obj);
+    selector(path).ok()?.first().map(|node| (*node).clone())
+}
+
+/// Substitutes variables in the query parameters and headers of a web request.
+fn substitute_http_options(options: &HttpOptions, state: &HashMap<String, String>) -> HttpOptions {
+    let substitute_pairs = |pairs: &[(String, String)]| {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.clone(), substitute_string(value, state)))
+            .collect()
+    };
+    HttpOptions {
+        query: substitute_pairs(&options.query),
+        headers: substitute_pairs(&options.headers),
+        ..options.clone()
+    }
+}
+
+/// Substitutes variables in a mock route. `${request...}` is left for the mock server,
+/// which fills it in from each request it answers.
+fn substitute_mock_route(route: &MockRoute, state: &HashMap<String, String>) -> MockRoute {
+    let response = match &route.response {
+        MockResponse::Respond {
+            status,
+            body,
+            headers,
+        } => MockResponse::Respond {
+            status: *status,
+            body: substitute_string(body, state),
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.clone(), substitute_string(value, state)))
+                .collect(),
+        },
+        MockResponse::CloseConnection => MockResponse::CloseConnection,
+    };
+    MockRoute {
+        path: substitute_string(&route.path, state),
+        response,
+        ..route.clone()
+    }
+}
+
 fn substitute_value(v: &Value, state: &HashMap<String, String>) -> Value {
     match v {
         Value::String(s) => Value::String(substitute_string(s, state)),
@@ -788,10 +1407,12 @@ pub fn is_synchronous(test_case: &TestCase) -> bool {
             action,
             Action::Run { .. }
                 | Action::SetCwd { .. }
+                | Action::Capture { .. }
                 | Action::CreateFile { .. }
                 | Action::DeleteFile { .. }
                 | Action::CreateDir { .. }
                 | Action::DeleteDir { .. }
+                | Action::CopyDir { .. }
                 | Action::ReadFile { .. }
                 | Action::HttpGet { .. }
                 | Action::HttpPost { .. }
@@ -804,6 +1425,8 @@ pub fn is_synchronous(test_case: &TestCase) -> bool {
                 | Action::HttpSetCookie { .. }
                 | Action::HttpClearCookie { .. }
                 | Action::HttpClearCookies
+                | Action::MockServe { .. }
+                | Action::Eventually { .. }
         ),
         WhenStep::TaskCall(_) => true, // Task calls are expanded before execution
     })
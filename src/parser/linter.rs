@@ -1,9 +1,15 @@
+use crate::backend::process::signal_number;
+use crate::backend::terminal_backend::key_sequence;
 use crate::parser::ast::{
-    Action, Condition, GivenStep, Scenario, Statement, TestCase, TestSuite, TestSuiteSettings,
-    ThenStep, Value, WhenStep,
+    Action, Condition, ExamplesTable, GivenStep, HttpOptions, MockResponse, Scenario,
+    ScenarioBodyItem, Statement, StdinSource, TestCase, TestSuite, TestSuiteSettings, ThenStep,
+    Value, WhenStep,
 };
 use std::collections::{HashMap, HashSet};
 
+/// Actors that are always available. Any other declared actor is a named terminal session.
+const BUILTIN_ACTORS: &[&str] = &["Web", "Terminal", "System", "FileSystem", "Mock"];
+
 // The E, W and I codes are inspired by ESLint's conventions.
 pub struct DiagnosticRule {
     pub code: &'static str,
@@ -30,6 +36,22 @@ impl DiagnosticCodes {
         code: "E004",
         message: "Request body is not valid JSON, but Content-Type is 'application/json'.",
     };
+    pub const EXAMPLES_ROW_WIDTH: DiagnosticRule = DiagnosticRule {
+        code: "E005",
+        message: "Examples row does not have the same number of columns as the header.",
+    };
+    pub const UNKNOWN_KEY: DiagnosticRule = DiagnosticRule {
+        code: "E006",
+        message: "Unknown key name for `Terminal presses`.",
+    };
+    pub const UNDECLARED_ACTOR: DiagnosticRule = DiagnosticRule {
+        code: "E007",
+        message: "Terminal actor is used but not declared in `actors`.",
+    };
+    pub const UNKNOWN_SIGNAL: DiagnosticRule = DiagnosticRule {
+        code: "E008",
+        message: "Unknown signal name, expected e.g. \"SIGTERM\" or \"SIGINT\".",
+    };
 
     // Warning codes (W) - Potential issues
     pub const SCENARIO_NO_TESTS: DiagnosticRule = DiagnosticRule {
@@ -137,8 +159,12 @@ struct Linter {
     used_vars: HashSet<String>,
     defined_actors: HashSet<String>,
     used_actors: HashSet<String>,
+    // Names given to background processes with `start ... as name`.
+    started_processes: HashSet<String>,
     seen_scenario_names: HashSet<String>,
     current_headers: HashMap<String, String>,
+    // Sandboxed scenarios run in a temp dir that is deleted afterwards.
+    sandbox: bool,
 }
 
 impl Linter {
@@ -149,8 +175,10 @@ impl Linter {
             used_vars: HashSet::new(),
             defined_actors: HashSet::new(),
             used_actors: HashSet::new(),
+            started_processes: HashSet::new(),
             seen_scenario_names: HashSet::new(),
             current_headers: HashMap::new(),
+            sandbox: false,
         }
     }
 
@@ -230,6 +258,22 @@ impl Linter {
         }
     }
 
+    fn check_signal(&mut self, signal: &str) {
+        if signal_number(signal).is_none() {
+            self.add_diagnostic(
+                &DiagnosticCodes::UNKNOWN_SIGNAL,
+                &format!(
+                    "{}: {} Found '{}'",
+                    DiagnosticCodes::UNKNOWN_SIGNAL.code,
+                    DiagnosticCodes::UNKNOWN_SIGNAL.message,
+                    signal
+                ),
+                0,
+                Severity::Error,
+            );
+        }
+    }
+
     fn lint_header(&mut self, key: &str, value: &str) {
         let lower_key = key.to_lowercase();
         // This regex ensures HTTP header names contain only valid "token" characters as defined by RFC 7230.
@@ -294,6 +338,15 @@ impl Linter {
             .insert(lower_key.to_owned(), value.to_owned());
     }
 
+    fn lint_http_options(&mut self, options: &HttpOptions) {
+        // Headers of a single request replace the ones set before and do not outlive it.
+        let headers = std::mem::take(&mut self.current_headers);
+        for (key, value) in &options.headers {
+            self.lint_header(key, value);
+        }
+        self.current_headers = headers;
+    }
+
     fn lint_http_body(&mut self, body: &str) {
         // Skip linting if the body contains variable substitution
         if body.contains("${") {
@@ -334,6 +387,27 @@ impl Linter {
     }
 
     // Use a custom formatted message
+    fn check_examples_table(&mut self, examples: &ExamplesTable, line: usize) {
+        for row in &examples.rows {
+            if row.len() != examples.headers.len() {
+                self.add_diagnostic(
+                    &DiagnosticCodes::EXAMPLES_ROW_WIDTH,
+                    &format!(
+                        "{}: {} Expected {} but found {} in row '{}' (line: {})",
+                        DiagnosticCodes::EXAMPLES_ROW_WIDTH.code,
+                        DiagnosticCodes::EXAMPLES_ROW_WIDTH.message,
+                        examples.headers.len(),
+                        row.len(),
+                        row.join(" | "),
+                        line
+                    ),
+                    line,
+                    Severity::Error,
+                );
+            }
+        }
+    }
+
     fn add_diagnostic(
         &mut self,
         rule: &DiagnosticRule,
@@ -422,6 +496,33 @@ impl Visitor for Linter {
             );
         }
 
+        // Named terminal actors (anything but the built-in actors and background processes)
+        // must be declared.
+        let mut undeclared_actors: Vec<String> = self
+            .used_actors
+            .iter()
+            .filter(|a| {
+                !BUILTIN_ACTORS.contains(&a.as_str())
+                    && !self.defined_actors.contains(*a)
+                    && !self.started_processes.contains(*a)
+            })
+            .cloned()
+            .collect();
+        undeclared_actors.sort();
+        for actor in undeclared_actors {
+            self.add_diagnostic(
+                &DiagnosticCodes::UNDECLARED_ACTOR,
+                &format!(
+                    "{}: {} ({})",
+                    DiagnosticCodes::UNDECLARED_ACTOR.code,
+                    DiagnosticCodes::UNDECLARED_ACTOR.message,
+                    actor
+                ),
+                0,
+                Severity::Error,
+            );
+        }
+
         // Fourth pass: check for unused actors
         let unused_actors: Vec<String> = self
             .defined_actors
@@ -459,6 +560,7 @@ impl Visitor for Linter {
     }
 
     fn visit_settings(&mut self, settings: &TestSuiteSettings) {
+        self.sandbox = settings.sandbox;
         let default_span = settings
             .span
             .as_ref()
@@ -558,7 +660,7 @@ impl Visitor for Linter {
         //println!("Scenario: {}", scenario.name);
 
         // Check for empty scenarios.
-        if scenario.tests.is_empty() {
+        if scenario.tests.is_empty() && scenario.body.is_empty() {
             self.add_diagnostic(
                 &DiagnosticCodes::SCENARIO_NO_TESTS,
                 &format!(
@@ -588,7 +690,7 @@ impl Visitor for Linter {
         }
 
         // Check if setup actions exist without a corresponding cleanup.
-        if scenario_has_setup_actions(scenario) && scenario.after.is_empty() {
+        if !self.sandbox && scenario_has_setup_actions(scenario) && scenario.after.is_empty() {
             self.add_diagnostic(
                 &DiagnosticCodes::MISSING_CLEANUP,
                 &format!(
@@ -602,9 +704,20 @@ impl Visitor for Linter {
             );
         }
 
+        if let Some(examples) = &scenario.examples {
+            self.check_examples_table(examples, line);
+        }
+
         for test in &scenario.tests {
             self.visit_test_case(test);
         }
+        for item in &scenario.body {
+            if let ScenarioBodyItem::Outline(outline) = item {
+                let line = outline.test.span.as_ref().map_or(0, |s| s.line);
+                self.check_examples_table(&outline.examples, line);
+                self.visit_test_case(&outline.test);
+            }
+        }
     }
 
     fn visit_test_case(&mut self, test: &TestCase) {
@@ -667,14 +780,66 @@ impl Visitor for Linter {
         };
 
         match action {
-            Action::Run { command, .. } => {
-                self.used_actors.insert("Terminal".to_string());
+            Action::Run {
+                actor,
+                command,
+                env,
+                stdin,
+                ..
+            } => {
+                self.used_actors.insert(actor.clone());
                 find_vars(command, &mut self.used_vars);
+                for (_, value) in env {
+                    find_vars(value, &mut self.used_vars);
+                }
+                if let Some(StdinSource::Text(input) | StdinSource::File(input)) = stdin {
+                    find_vars(input, &mut self.used_vars);
+                }
             }
-            Action::SetCwd { path } => {
-                self.used_actors.insert("Terminal".to_string());
+            Action::Spawn { actor, command } => {
+                self.used_actors.insert(actor.clone());
+                find_vars(command, &mut self.used_vars);
+            }
+            Action::SetCwd { actor, path } => {
+                self.used_actors.insert(actor.clone());
                 find_vars(path, &mut self.used_vars);
             }
+            Action::Start {
+                actor,
+                command,
+                name,
+            } => {
+                self.used_actors.insert(actor.clone());
+                self.started_processes.insert(name.clone());
+                find_vars(command, &mut self.used_vars);
+            }
+            Action::Stop { actor, .. } | Action::Capture { actor, .. } => {
+                self.used_actors.insert(actor.clone());
+            }
+            Action::SendSignal { actor, signal, .. } => {
+                self.used_actors.insert(actor.clone());
+                self.check_signal(signal);
+            }
+            Action::Type { actor, text } => {
+                self.used_actors.insert(actor.clone());
+                find_vars(text, &mut self.used_vars);
+            }
+            Action::Press { actor, key } => {
+                self.used_actors.insert(actor.clone());
+                if key_sequence(key).is_none() {
+                    self.add_diagnostic(
+                        &DiagnosticCodes::UNKNOWN_KEY,
+                        &format!(
+                            "{}: {} Found '{}'",
+                            DiagnosticCodes::UNKNOWN_KEY.code,
+                            DiagnosticCodes::UNKNOWN_KEY.message,
+                            key
+                        ),
+                        0,
+                        Severity::Error,
+                    );
+                }
+            }
             Action::CreateFile { path, content } => {
                 self.used_actors.insert("FileSystem".to_string());
                 find_vars(path, &mut self.used_vars);
@@ -690,25 +855,62 @@ impl Visitor for Linter {
                 self.used_actors.insert("FileSystem".to_string());
                 find_vars(path, &mut self.used_vars);
             }
+            Action::CopyDir {
+                source,
+                destination,
+            } => {
+                self.used_actors.insert("FileSystem".to_string());
+                find_vars(source, &mut self.used_vars);
+                find_vars(destination, &mut self.used_vars);
+            }
             Action::HttpSetHeader { key, value } | Action::HttpSetCookie { key, value } => {
                 self.used_actors.insert("Web".to_string());
                 find_vars(key, &mut self.used_vars);
                 find_vars(value, &mut self.used_vars);
                 self.lint_header(key, value);
             }
-            Action::HttpGet { url } | Action::HttpDelete { url } => {
+            Action::HttpGet {
+                url,
+                options,
+                until,
+            } => {
                 self.used_actors.insert("Web".to_string());
                 find_vars(url, &mut self.used_vars);
                 self.lint_url(url);
+                for (_, value) in options.query.iter().chain(&options.headers) {
+                    find_vars(value, &mut self.used_vars);
+                }
+                self.lint_http_options(options);
+                if let Some(poll) = until {
+                    self.visit_condition(&poll.condition);
+                }
             }
-            Action::HttpPost { url, body }
-            | Action::HttpPut { url, body }
-            | Action::HttpPatch { url, body } => {
+            Action::HttpDelete { url, options } => {
+                self.used_actors.insert("Web".to_string());
+                find_vars(url, &mut self.used_vars);
+                self.lint_url(url);
+                for (_, value) in options.query.iter().chain(&options.headers) {
+                    find_vars(value, &mut self.used_vars);
+                }
+                self.lint_http_options(options);
+            }
+            Action::Eventually { actions, .. } => {
+                for action in actions {
+                    self.visit_action(action);
+                }
+            }
+            Action::HttpPost { url, body, options }
+            | Action::HttpPut { url, body, options }
+            | Action::HttpPatch { url, body, options } => {
                 self.used_actors.insert("Web".to_string());
                 find_vars(url, &mut self.used_vars);
                 find_vars(body, &mut self.used_vars);
                 self.lint_url(url);
                 self.lint_http_body(body);
+                for (_, value) in options.query.iter().chain(&options.headers) {
+                    find_vars(value, &mut self.used_vars);
+                }
+                self.lint_http_options(options);
             }
             Action::Pause { .. }
             | Action::Log { .. }
@@ -716,6 +918,28 @@ impl Visitor for Linter {
             | Action::Uuid { .. } => {
                 self.used_actors.insert("System".to_string());
             }
+            Action::MockServe { routes, .. } => {
+                self.used_actors.insert("Mock".to_string());
+                for route in routes {
+                    find_vars(&route.path, &mut self.used_vars);
+                    if let MockResponse::Respond { status, body, .. } = &route.response {
+                        find_vars(body, &mut self.used_vars);
+                        if !(100..=599).contains(status) {
+                            self.add_diagnostic(
+                                &DiagnosticCodes::INVALID_HTTP_STATUS,
+                                &format!(
+                                    "{}: {} ({})",
+                                    DiagnosticCodes::INVALID_HTTP_STATUS.code,
+                                    DiagnosticCodes::INVALID_HTTP_STATUS.message,
+                                    status
+                                ),
+                                0,
+                                Severity::Error,
+                            );
+                        }
+                    }
+                }
+            }
             // Other actions...
             _ => {}
         }
@@ -751,16 +975,44 @@ impl Visitor for Linter {
                 }
             }
 
+            Condition::TerminalActor { actor, condition } => {
+                // The inner condition records the default `Terminal` as used; it is not.
+                let terminal_was_used = self.used_actors.contains("Terminal");
+                self.visit_condition(condition);
+                if !terminal_was_used {
+                    self.used_actors.remove("Terminal");
+                }
+                self.used_actors.insert(actor.clone());
+            }
+
             Condition::OutputContains { text, .. }
             | Condition::OutputNotContains { text, .. }
             | Condition::StderrContains(text)
             | Condition::OutputStartsWith(text)
             | Condition::OutputEndsWith(text)
-            | Condition::OutputEquals(text) => {
+            | Condition::OutputEquals(text)
+            | Condition::ScreenContains(text)
+            | Condition::ScreenLineEquals { text, .. }
+            | Condition::ScreenMatchesSnapshot { name: text }
+            | Condition::OutputMatchesSnapshot { name: text }
+            | Condition::StreamContains { text, .. }
+            | Condition::StreamEquals { text, .. }
+            | Condition::StreamLineEquals { text, .. }
+            | Condition::StreamMatches { regex: text, .. } => {
                 self.used_actors.insert("Terminal".to_string());
                 find_cond_vars(text);
             }
 
+            Condition::LastCommandKilledBy(signal) | Condition::KilledBy(signal) => {
+                self.check_signal(signal);
+            }
+
+            Condition::PrintedBefore { first, second } => {
+                self.used_actors.insert("Terminal".to_string());
+                find_cond_vars(first);
+                find_cond_vars(second);
+            }
+
             Condition::OutputMatches { regex, .. } => {
                 self.used_actors.insert("Terminal".to_string());
                 find_cond_vars(regex);
@@ -786,7 +1038,9 @@ impl Visitor for Linter {
             Condition::JsonValueHasSize { path, .. }
             | Condition::JsonOutputAtEquals { path, .. }
             | Condition::JsonOutputAtIncludes { path, .. }
-            | Condition::JsonOutputAtHasItemCount { path, .. } => {
+            | Condition::JsonOutputAtHasItemCount { path, .. }
+            | Condition::JsonOutputAtCompare { path, .. }
+            | Condition::JsonPathCompare { path, .. } => {
                 find_cond_vars(path);
             }
 
@@ -799,6 +1053,11 @@ impl Visitor for Linter {
                 self.used_actors.insert("FileSystem".to_string());
                 find_cond_vars(path);
             }
+            Condition::FileMatchesSnapshot { path, name } => {
+                self.used_actors.insert("FileSystem".to_string());
+                find_cond_vars(path);
+                find_cond_vars(name);
+            }
 
             Condition::ResponseStatusIs(status) => {
                 self.used_actors.insert("Web".to_string());
@@ -841,6 +1100,37 @@ impl Visitor for Linter {
             Condition::ResponseBodyMatches { regex, .. } => {
                 find_cond_vars(regex);
             }
+            Condition::ResponseHeaderExists { name } | Condition::CookieExists { name } => {
+                find_cond_vars(name);
+            }
+            Condition::ResponseHeaderEquals { name, value }
+            | Condition::ResponseHeaderContains { name, value }
+            | Condition::CookieEquals { name, value }
+            | Condition::ResponseHeaderMatches {
+                name, regex: value, ..
+            } => {
+                find_cond_vars(name);
+                find_cond_vars(value);
+            }
+            Condition::ResponseBodyMatchesSnapshot { name } => {
+                find_cond_vars(name);
+            }
+            Condition::MockReceived { path: text, .. }
+            | Condition::MockLastRequestBodyContains { text }
+            | Condition::MockLastRequestHeaderEquals { value: text, .. } => {
+                self.used_actors.insert("Mock".to_string());
+                find_cond_vars(text);
+            }
+            Condition::MockLastRequestJsonPathEquals {
+                path,
+                expected_value,
+            } => {
+                self.used_actors.insert("Mock".to_string());
+                find_cond_vars(path);
+                if let Value::String(s) = expected_value {
+                    find_cond_vars(s);
+                }
+            }
 
             // Other conditions...
             _ => {}
@@ -920,8 +1210,6 @@ impl Visitor for Linter {
     }
 
     fn visit_actor_def(&mut self, actors: &Vec<String>) {
-        const VALID_ACTORS: &[&str] = &["Web", "Terminal", "System", "FileSystem"];
-
         let mut seen_actors = HashSet::new();
 
         for actor in actors {
@@ -939,20 +1227,6 @@ impl Visitor for Linter {
                 );
             }
 
-            // Check if actor is valid
-            if !VALID_ACTORS.contains(&actor.as_str()) {
-                self.add_diagnostic(
-                    &DiagnosticCodes::BEST_PRACTICE_SUGGESTION,
-                    &format!(
-                        "Unknown actor '{}'. Valid actors are: {}",
-                        actor,
-                        VALID_ACTORS.join(", ")
-                    ),
-                    0,
-                    Severity::Error,
-                );
-            }
-
             // Check naming convention (PascalCase)
             if !actor.chars().next().unwrap_or(' ').is_uppercase() {
                 self.add_diagnostic(
@@ -1,7 +1,11 @@
+use crate::backend::snapshot::is_valid_redaction;
+use crate::error::AppError;
 use crate::parser::ast::{
-    Action, Condition, ForeachBlock, GivenStep, ReportFormat, Scenario, ScenarioBodyItem,
-    ScenarioSpan, SettingSpan, Span, StateCondition, Statement, TaskArg, TaskBodyItem, TaskCall,
-    TaskDef, TestCase, TestCaseSpan, TestSuite, TestSuiteSettings, ThenStep, Value, WhenStep,
+    Action, CaptureSource, Condition, ExamplesTable, ForeachBlock, GivenStep, HttpOptions,
+    HttpPoll, JsonComparison, MockResponse, MockRoute, OutputStream, ReportFormat, Scenario,
+    ScenarioBodyItem, ScenarioSpan, SettingSpan, Span, StateCondition, Statement, StdinSource,
+    TaskArg, TaskBodyItem, TaskCall, TaskDef, TestCase, TestCaseSpan, TestOutline, TestSuite,
+    TestSuiteSettings, ThenStep, Value, WhenStep,
 };
 use crate::parser::helpers::{
     substitute_string, substitute_variables_in_given_step, substitute_variables_in_test_case,
@@ -11,6 +15,7 @@ use pest::Parser;
 use pest::iterators::{Pair, Pairs};
 use pest_derive::Parser;
 use std::collections::HashMap;
+use std::iter;
 
 #[derive(Parser, Debug)]
 #[grammar = "parser/choreo.pest"]
@@ -19,6 +24,7 @@ pub struct ChoreoParser;
 /// Parses a source string into an Abstract Syntax Tree (AST).
 pub fn parse(source: &str) -> Result<TestSuite, pest::error::Error<Rule>> {
     let pairs = ChoreoParser::parse(Rule::grammar, source)?.next().unwrap();
+    check_exit_codes(&pairs)?;
 
     let mut statements = vec![];
     for statement_pair in pairs.into_inner() {
@@ -37,7 +43,7 @@ pub fn parse(source: &str) -> Result<TestSuite, pest::error::Error<Rule>> {
             Rule::env_def => build_env_def(pair),
             Rule::var_def => build_var_def(pair),
             Rule::feature_def => build_feature_def(pair),
-            Rule::scenario_def => build_scenario(pair),
+            Rule::scenario_def | Rule::scenario_outline_def => build_scenario(pair),
             Rule::background_def => build_background_def(pair),
             Rule::task_def => build_task_def(pair),
             _ => unimplemented!("Parser rule not handled: {:?}", pair.as_rule()),
@@ -47,17 +53,31 @@ pub fn parse(source: &str) -> Result<TestSuite, pest::error::Error<Rule>> {
     Ok(TestSuite { statements })
 }
 
+/// Rejects exit codes in conditions that are too large to ever match a process's exit code.
+fn check_exit_codes(pair: &Pair<Rule>) -> Result<(), pest::error::Error<Rule>> {
+    let conditions = pair.clone().into_inner().flatten().filter(|p| {
+        matches!(
+            p.as_rule(),
+            Rule::last_command_exit_code_is_cond | Rule::exited_with_condition
+        )
+    });
+    for condition in conditions {
+        let code = condition.into_inner().next().unwrap();
+        if code.as_str().parse::<i32>().is_err() {
+            return Err(pest::error::Error::new_from_span(
+                pest::error::ErrorVariant::CustomError {
+                    message: format!("exit code {} is out of range", code.as_str()),
+                },
+                code.as_span(),
+            ));
+        }
+    }
+    Ok(())
+}
+
 // Helper function for an import definition.
 fn build_import_def(pair: Pair<Rule>) -> Statement {
-    let path = pair
-        .into_inner()
-        .next()
-        .unwrap()
-        .into_inner()
-        .next()
-        .unwrap()
-        .as_str()
-        .to_string();
+    let path = string_content(pair.into_inner().next().unwrap());
     Statement::Import(path)
 }
 
@@ -88,6 +108,13 @@ fn build_settings_def(pair: Pair<Rule>) -> Statement {
         shell_path_span: None,
         stop_on_failure_span: None,
         expected_failures_span: None,
+        env_overrides_span: None,
+        terminal_rows_span: None,
+        terminal_cols_span: None,
+        snapshot_redactions_span: None,
+        sandbox_span: None,
+        http_timeout_span: None,
+        http_follow_redirects_span: None,
     };
 
     // Store the span information
@@ -114,10 +141,10 @@ fn build_settings_def(pair: Pair<Rule>) -> Statement {
         match key {
             "timeout_seconds" => {
                 setting_spans.timeout_seconds_span = Some(span_info);
-                if let Value::Number(n) = build_value(value_pair) {
+                if let Value::Number(n @ 0..) = build_value(value_pair) {
                     settings.timeout_seconds = n as u64;
                 } else {
-                    panic!("'timeout_seconds' setting must be a number");
+                    panic!("'timeout_seconds' setting must be a non-negative number");
                 }
             }
             "report_path" => {
@@ -170,10 +197,78 @@ fn build_settings_def(pair: Pair<Rule>) -> Statement {
             }
             "expected_failures" => {
                 setting_spans.expected_failures_span = Some(span_info);
-                if let Value::Number(n) = build_value(value_pair) {
+                if let Value::Number(n @ 0..) = build_value(value_pair) {
                     settings.expected_failures = n as usize;
                 } else {
-                    panic!("'expected_failures' setting must be a number");
+                    panic!("'expected_failures' setting must be a non-negative number");
+                }
+            }
+            "terminal_rows" => {
+                setting_spans.terminal_rows_span = Some(span_info);
+                if let Value::Number(n @ 1..=0xFFFF) = build_value(value_pair) {
+                    settings.terminal_rows = n as u16;
+                } else {
+                    panic!("'terminal_rows' setting must be a positive number");
+                }
+            }
+            "terminal_cols" => {
+                setting_spans.terminal_cols_span = Some(span_info);
+                if let Value::Number(n @ 1..=0xFFFF) = build_value(value_pair) {
+                    settings.terminal_cols = n as u16;
+                } else {
+                    panic!("'terminal_cols' setting must be a positive number");
+                }
+            }
+            "env_overrides" => {
+                setting_spans.env_overrides_span = Some(span_info);
+                if let Value::Object(map) = build_value(value_pair) {
+                    settings.env_overrides = object_pairs(map);
+                } else {
+                    panic!("'env_overrides' setting must be an object, e.g. {{ KEY: \"value\" }}");
+                }
+            }
+            "snapshot_redactions" => {
+                setting_spans.snapshot_redactions_span = Some(span_info);
+                if let Value::Array(rules) = build_value(value_pair) {
+                    settings.snapshot_redactions = rules.iter().map(Value::as_string).collect();
+                    if let Some(rule) = settings
+                        .snapshot_redactions
+                        .iter()
+                        .find(|rule| !is_valid_redaction(rule))
+                    {
+                        panic!(
+                            "Invalid 'snapshot_redactions' rule '{}': not a valid regex",
+                            rule
+                        );
+                    }
+                } else {
+                    panic!(
+                        "'snapshot_redactions' setting must be an array of rule names or regexes"
+                    );
+                }
+            }
+            "sandbox" => {
+                setting_spans.sandbox_span = Some(span_info);
+                if let Value::Bool(b) = build_value(value_pair) {
+                    settings.sandbox = b;
+                } else {
+                    panic!("'sandbox' setting must be a boolean (true/false)");
+                }
+            }
+            "http_timeout" => {
+                setting_spans.http_timeout_span = Some(span_info);
+                match build_value(value_pair) {
+                    Value::Number(n @ 1..) => settings.http_timeout = Some(n as f32),
+                    Value::Float(n) if n > 0.0 => settings.http_timeout = Some(n as f32),
+                    _ => panic!("'http_timeout' setting must be a positive number of seconds"),
+                }
+            }
+            "http_follow_redirects" => {
+                setting_spans.http_follow_redirects_span = Some(span_info);
+                if let Value::Bool(b) = build_value(value_pair) {
+                    settings.http_follow_redirects = b;
+                } else {
+                    panic!("'http_follow_redirects' setting must be a boolean (true/false)");
                 }
             }
             _ => { /* Ignore unknown settings */ }
@@ -196,15 +291,7 @@ fn build_var_def(pair: Pair<Rule>) -> Statement {
 
 // Helper function for a feature definition.
 fn build_feature_def(pair: Pair<Rule>) -> Statement {
-    let name = pair
-        .into_inner()
-        .next()
-        .unwrap()
-        .into_inner()
-        .next()
-        .unwrap()
-        .as_str()
-        .to_string();
+    let name = string_content(pair.into_inner().next().unwrap());
     Statement::FeatureDef(name)
 }
 
@@ -300,8 +387,8 @@ fn build_task_arg(pair: Pair<Rule>) -> TaskArg {
             TaskArg::Duration(duration)
         }
         Rule::string => {
-            let s = inner.into_inner().next().unwrap().as_str();
-            TaskArg::String(unescape_string(s))
+            let s = string_content(inner);
+            TaskArg::String(unescape_string(&s))
         }
         Rule::number => {
             let n: i32 = inner.as_str().parse().unwrap_or(0);
@@ -345,6 +432,8 @@ fn build_scenario(pair: Pair<Rule>) -> Statement {
         column: span.start_pos().line_col().1,
     });
 
+    scenario.tags = build_tags(&mut inner);
+
     // Peek and look for the parallel keyword
     if let Some(token) = inner.peek() {
         if token.as_rule() == Rule::parallel_keyword {
@@ -361,7 +450,7 @@ fn build_scenario(pair: Pair<Rule>) -> Statement {
         line: name_pair.as_span().start_pos().line_col().0,
         column: name_pair.as_span().start_pos().line_col().1,
     });
-    scenario.name = unescape_string(name_pair.into_inner().next().unwrap().as_str());
+    scenario.name = unescape_string(&string_content(name_pair));
 
     let mut body_items = Vec::new();
 
@@ -388,6 +477,10 @@ fn build_scenario(pair: Pair<Rule>) -> Statement {
                                 let foreach_block = build_foreach_block(scenario_body_item);
                                 body_items.push(ScenarioBodyItem::Foreach(foreach_block));
                             }
+                            Rule::test_outline => {
+                                let outline = build_test_outline(scenario_body_item);
+                                body_items.push(ScenarioBodyItem::Outline(outline));
+                            }
                             _ => {}
                         }
                     }
@@ -398,6 +491,9 @@ fn build_scenario(pair: Pair<Rule>) -> Statement {
                 body_items.push(ScenarioBodyItem::Test(test_case.clone()));
                 scenario.tests.push(test_case);
             }
+            Rule::examples_block => {
+                scenario.examples = Some(build_examples_table(item));
+            }
             Rule::after_block => {
                 scenario_spans.after_span = Some(span_info);
                 scenario.after = build_when_steps(item.into_inner());
@@ -411,6 +507,19 @@ fn build_scenario(pair: Pair<Rule>) -> Statement {
     Statement::Scenario(scenario)
 }
 
+/// Consumes leading `@tag` pairs and returns the tag names without the `@`.
+fn build_tags(inner: &mut Pairs<Rule>) -> Vec<String> {
+    let mut tags = Vec::new();
+    while let Some(pair) = inner.peek() {
+        if pair.as_rule() != Rule::tag {
+            break;
+        }
+        tags.push(pair.as_str().trim_start_matches('@').to_string());
+        inner.next();
+    }
+    tags
+}
+
 /// Builds a TestCase from a parsed Pair.
 pub fn build_test_case(pair: Pair<Rule>) -> TestCase {
     let span = pair.as_span();
@@ -423,6 +532,7 @@ pub fn build_test_case(pair: Pair<Rule>) -> TestCase {
         then_span: None,
     };
 
+    let tags = build_tags(&mut inner);
     let name_pair = inner.next().unwrap();
     let name = name_pair.as_str().to_string();
     testcase_spans.name_span = Some(Span {
@@ -434,12 +544,7 @@ pub fn build_test_case(pair: Pair<Rule>) -> TestCase {
 
     let description_pair = inner.next().unwrap();
     let description_span = description_pair.as_span();
-    let description = description_pair
-        .into_inner()
-        .next()
-        .unwrap()
-        .as_str()
-        .to_string();
+    let description = string_content(description_pair);
     testcase_spans.description_span = Some(Span {
         start: description_span.start(),
         end: description_span.end(),
@@ -480,6 +585,7 @@ pub fn build_test_case(pair: Pair<Rule>) -> TestCase {
         given: build_given_steps(given_block.into_inner()),
         when: build_when_steps(when_block.into_inner()),
         then: build_then_steps(then_block.into_inner()),
+        tags,
         span: Some(Span {
             start: span.start(),
             end: span.end(),
@@ -490,6 +596,36 @@ pub fn build_test_case(pair: Pair<Rule>) -> TestCase {
     }
 }
 
+/// Builds a test outline; the test template is followed by its examples table.
+fn build_test_outline(pair: Pair<Rule>) -> TestOutline {
+    let examples = pair
+        .clone()
+        .into_inner()
+        .find(|p| p.as_rule() == Rule::examples_block)
+        .map(build_examples_table)
+        .unwrap_or_default();
+
+    TestOutline {
+        test: build_test_case(pair),
+        examples,
+    }
+}
+
+/// Builds an examples table. The first row holds the column names.
+fn build_examples_table(pair: Pair<Rule>) -> ExamplesTable {
+    let mut rows = pair.into_inner().map(|row| {
+        row.into_inner()
+            .map(|cell| cell.as_str().trim().replace("\\|", "|"))
+            .collect::<Vec<String>>()
+    });
+    let headers = rows.next().unwrap_or_default();
+
+    ExamplesTable {
+        headers,
+        rows: rows.collect(),
+    }
+}
+
 fn build_foreach_block(pair: Pair<Rule>) -> ForeachBlock {
     let mut inner = pair.into_inner();
     let loop_variable = inner.next().unwrap().as_str().to_string();
@@ -507,7 +643,7 @@ fn build_foreach_block(pair: Pair<Rule>) -> ForeachBlock {
 pub fn expand_scenario_foreach_blocks(
     s: &Scenario,
     env_vars: &HashMap<String, String>,
-) -> Scenario {
+) -> Result<Scenario, AppError> {
     let mut expanded_body = Vec::new();
 
     for item in &s.body {
@@ -600,10 +736,15 @@ pub fn expand_scenario_foreach_blocks(
                     }
                 }
             }
+            ScenarioBodyItem::Outline(outline) => {
+                for test in expand_test_outline(outline, env_vars)? {
+                    expanded_body.push(ScenarioBodyItem::Test(test));
+                }
+            }
         }
     }
 
-    Scenario {
+    Ok(Scenario {
         name: s.name.clone(),
         tests: expanded_body
             .iter()
@@ -615,16 +756,18 @@ pub fn expand_scenario_foreach_blocks(
         body: expanded_body,
         after: s.after.clone(),
         parallel: s.parallel,
+        tags: s.tags.clone(),
+        examples: s.examples.clone(),
         span: s.span.clone(),
         scenario_span: s.scenario_span.clone(),
-    }
+    })
 }
 
 /// Expands `foreach` blocks within a scenario into a flat list of `TestCase`s.
 pub fn _expand_foreach_blocks(
     scenario: &Scenario,
     variables: &HashMap<String, String>,
-) -> Vec<TestCase> {
+) -> Result<Vec<TestCase>, AppError> {
     let mut new_scenario = scenario.clone();
     let mut expanded_tests = Vec::new();
 
@@ -660,16 +803,19 @@ pub fn _expand_foreach_blocks(
                     }
                 }
             }
+            ScenarioBodyItem::Outline(outline) => {
+                expanded_tests.extend(expand_test_outline(outline, variables)?);
+            }
         }
     }
     new_scenario.tests = expanded_tests;
-    new_scenario.tests
+    Ok(new_scenario.tests)
 }
 
 pub fn expand_foreach_blocks(
     scenario: &Scenario,
     variables: &HashMap<String, String>,
-) -> Vec<TestCase> {
+) -> Result<Vec<TestCase>, AppError> {
     // Start with already parsed top-level tests (explicit tests before any foreach)
     let mut expanded = scenario.tests.clone();
 
@@ -745,10 +891,127 @@ pub fn expand_foreach_blocks(
                     }
                 }
             }
+            ScenarioBodyItem::Outline(outline) => {
+                expanded.extend(expand_test_outline(outline, variables)?);
+            }
         }
     }
 
-    expanded
+    Ok(expanded)
+}
+
+/// Fails if a row of the examples table does not have a value for every column.
+fn check_examples_rows(examples: &ExamplesTable, outline: &str) -> Result<(), AppError> {
+    for (i, row) in examples.rows.iter().enumerate() {
+        if row.len() != examples.headers.len() {
+            return Err(AppError::ParseError(format!(
+                "Examples row {} of '{}' has {} column(s), but the header has {}",
+                i + 1,
+                outline,
+                row.len(),
+                examples.headers.len()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Expands a test outline into one test per examples row. Each row's test is named after the
+/// substituted test name, or `Name_<row>` when the name has no placeholders.
+pub fn expand_test_outline(
+    outline: &TestOutline,
+    variables: &HashMap<String, String>,
+) -> Result<Vec<TestCase>, AppError> {
+    check_examples_rows(&outline.examples, &outline.test.name)?;
+
+    let tests = outline
+        .examples
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut row_scope = variables.clone();
+            for (column, value) in outline.examples.bindings(row) {
+                row_scope.insert(column.clone(), value.clone());
+            }
+            let mut test = substitute_variables_in_test_case(&outline.test, &row_scope);
+            if test.name == outline.test.name {
+                test.name = format!("{}_{}", outline.test.name, i + 1);
+            }
+            test
+        })
+        .collect();
+    Ok(tests)
+}
+
+/// Expands a `scenario outline` into one scenario per examples row. Other scenarios are
+/// returned unchanged. Each row's scenario is named after the substituted scenario name, or
+/// gets the row values appended when the name has no placeholders.
+pub fn expand_scenario_outline(scenario: &Scenario) -> Result<Vec<Scenario>, AppError> {
+    let Some(examples) = &scenario.examples else {
+        return Ok(vec![scenario.clone()]);
+    };
+    check_examples_rows(examples, &scenario.name)?;
+
+    let scenarios = examples
+        .rows
+        .iter()
+        .map(|row| {
+            let row_scope: HashMap<String, String> = examples
+                .bindings(row)
+                .map(|(column, value)| (column.clone(), value.clone()))
+                .collect();
+            let mut name = substitute_string(&scenario.name, &row_scope);
+            if name == scenario.name {
+                name = format!("{} [{}]", scenario.name, row.join(", "));
+            }
+
+            Scenario {
+                name,
+                tests: scenario
+                    .tests
+                    .iter()
+                    .map(|t| substitute_variables_in_test_case(t, &row_scope))
+                    .collect(),
+                body: scenario
+                    .body
+                    .iter()
+                    .map(|item| substitute_variables_in_body_item(item, &row_scope))
+                    .collect(),
+                after: scenario
+                    .after
+                    .iter()
+                    .map(|step| substitute_variables_in_when_step(step, &row_scope))
+                    .collect(),
+                examples: None,
+                ..scenario.clone()
+            }
+        })
+        .collect();
+    Ok(scenarios)
+}
+
+fn substitute_variables_in_body_item(
+    item: &ScenarioBodyItem,
+    state: &HashMap<String, String>,
+) -> ScenarioBodyItem {
+    match item {
+        ScenarioBodyItem::Test(test) => {
+            ScenarioBodyItem::Test(substitute_variables_in_test_case(test, state))
+        }
+        ScenarioBodyItem::Foreach(foreach_block) => ScenarioBodyItem::Foreach(ForeachBlock {
+            tests: foreach_block
+                .tests
+                .iter()
+                .map(|t| substitute_variables_in_test_case(t, state))
+                .collect(),
+            ..foreach_block.clone()
+        }),
+        ScenarioBodyItem::Outline(outline) => ScenarioBodyItem::Outline(TestOutline {
+            test: substitute_variables_in_test_case(&outline.test, state),
+            examples: outline.examples.clone(),
+        }),
+    }
 }
 
 // Builds a vector of GivenSteps, which can be either an Action, Condition, or TaskCall.
@@ -819,34 +1082,46 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
         }
         Rule::terminal_condition => {
             let mut inner = inner_cond.into_inner();
+            let actor = inner.next().unwrap().as_str().to_string();
             let terminal_cond = inner.next().unwrap();
-            build_condition_from_specific(terminal_cond)
+            let condition = match build_condition_from_specific(terminal_cond) {
+                Condition::OutputContains { text, .. } => Condition::OutputContains {
+                    actor: actor.clone(),
+                    text,
+                },
+                Condition::OutputNotContains { text, .. } => Condition::OutputNotContains {
+                    actor: actor.clone(),
+                    text,
+                },
+                Condition::OutputMatches {
+                    regex, capture_as, ..
+                } => Condition::OutputMatches {
+                    actor: actor.clone(),
+                    regex,
+                    capture_as,
+                },
+                other => other,
+            };
+            if actor == "Terminal" {
+                condition
+            } else {
+                Condition::TerminalActor {
+                    actor,
+                    condition: Box::new(condition),
+                }
+            }
         }
         Rule::output_contains_condition => {
             let mut inner = inner_cond.into_inner();
             //let actor = inner.next().unwrap().as_str().to_string();
             let actor = "Terminal".to_string(); // Default actor for terminal conditions
-            let text = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let text = string_content(inner.next().unwrap());
             Condition::OutputContains { actor, text }
         }
         Rule::output_not_contains_condition => {
             let mut inner = inner_cond.into_inner();
             let actor = "Terminal".to_string();
-            let text = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let text = string_content(inner.next().unwrap());
             Condition::OutputNotContains { actor, text }
         }
         Rule::state_condition => {
@@ -882,14 +1157,7 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
         Rule::output_matches_condition => {
             let mut inner = inner_cond.into_inner();
             //let actor = inner.next().unwrap().as_str().to_string();
-            let regex = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let regex = string_content(inner.next().unwrap());
             let capture_as = inner.next().map(|p| p.as_str().to_string());
             Condition::OutputMatches {
                 actor: "Terminal".to_string(),
@@ -902,51 +1170,98 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
         Rule::last_command_exit_code_is_cond => {
             let mut inner = inner_cond.into_inner();
             let code_str = inner.next().unwrap().as_str();
-            let code: i32 = code_str.parse().unwrap();
+            let code: i64 = code_str.parse().unwrap();
             Condition::LastCommandExitCodeIs(code)
         }
+        Rule::last_command_was_killed_by_cond => {
+            let signal = string_content(inner_cond.into_inner().next().unwrap());
+            Condition::LastCommandKilledBy(signal)
+        }
+        Rule::last_command_timed_out_cond => Condition::LastCommandTimedOut,
+        Rule::last_command_duration_below_cond => {
+            let duration = parse_duration(inner_cond.into_inner().next().unwrap().as_str());
+            Condition::LastCommandDurationBelow { duration }
+        }
+        Rule::was_killed_by_condition => {
+            let signal = string_content(inner_cond.into_inner().next().unwrap());
+            Condition::KilledBy(signal)
+        }
+        Rule::is_running_condition => Condition::IsRunning,
+        Rule::screen_contains_condition => {
+            let text = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
+            Condition::ScreenContains(text)
+        }
+        Rule::screen_line_equals_condition => {
+            let mut inner = inner_cond.into_inner();
+            let line = inner.next().unwrap().as_str().parse().unwrap();
+            let text = unescape_string(&string_content(inner.next().unwrap()));
+            Condition::ScreenLineEquals { line, text }
+        }
+        Rule::cursor_at_condition => {
+            let mut inner = inner_cond.into_inner();
+            let row = inner.next().unwrap().as_str().parse().unwrap();
+            let col = inner.next().unwrap().as_str().parse().unwrap();
+            Condition::CursorAt { row, col }
+        }
+        Rule::screen_matches_snapshot_condition => {
+            let name = string_content(inner_cond.into_inner().next().unwrap());
+            Condition::ScreenMatchesSnapshot { name }
+        }
+        Rule::output_matches_snapshot_condition => {
+            let name = string_content(inner_cond.into_inner().next().unwrap());
+            Condition::OutputMatchesSnapshot { name }
+        }
+        Rule::exited_with_condition => {
+            let code = inner_cond.into_inner().next().unwrap().as_str();
+            Condition::ExitedWith(code.parse().unwrap())
+        }
         Rule::output_is_valid_json_condition => Condition::OutputIsValidJson,
         Rule::json_output_has_path_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner.next().unwrap());
             Condition::JsonOutputHasPath { path }
         }
         Rule::json_output_at_equals_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = inner.next().unwrap().as_str().to_string();
+            let path = string_content(inner.next().unwrap());
             let value = build_value(inner.next().unwrap());
             Condition::JsonOutputAtEquals { path, value }
         }
         Rule::json_output_at_includes_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = inner.next().unwrap().as_str().to_string();
+            let path = string_content(inner.next().unwrap());
             let value = build_value(inner.next().unwrap());
             Condition::JsonOutputAtIncludes { path, value }
         }
         Rule::json_output_at_has_item_count_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = inner.next().unwrap().as_str().to_string();
+            let path = string_content(inner.next().unwrap());
             let count_str = inner.next().unwrap().as_str();
             let count: i32 = count_str.parse().unwrap();
             Condition::JsonOutputAtHasItemCount { path, count }
         }
+        Rule::json_output_at_compare_condition => {
+            let mut inner = inner_cond.into_inner();
+            let path = string_content(inner.next().unwrap());
+            let comparison = build_json_comparison(inner.next().unwrap());
+            Condition::JsonOutputAtCompare { path, comparison }
+        }
         Rule::file_is_empty_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = unescape_string(inner.next().unwrap().into_inner().next().unwrap().as_str());
+            let path = unescape_string(&string_content(inner.next().unwrap()));
             Condition::FileIsEmpty { path }
         }
         Rule::file_is_not_empty_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = unescape_string(inner.next().unwrap().into_inner().next().unwrap().as_str());
+            let path = unescape_string(&string_content(inner.next().unwrap()));
             Condition::FileIsNotEmpty { path }
         }
+        Rule::file_matches_snapshot_condition => {
+            let mut inner = inner_cond.into_inner();
+            let path = unescape_string(&string_content(inner.next().unwrap()));
+            let name = string_content(inner.next().unwrap());
+            Condition::FileMatchesSnapshot { path, name }
+        }
         Rule::filesystem_condition => {
             let mut inner = inner_cond.into_inner();
             let next_pair = inner.next().unwrap();
@@ -955,17 +1270,11 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
             match next_pair.as_rule() {
                 Rule::file_is_empty_condition => build_condition_from_specific(next_pair),
                 Rule::file_is_not_empty_condition => build_condition_from_specific(next_pair),
+                Rule::file_matches_snapshot_condition => build_condition_from_specific(next_pair),
                 _ => {
                     // This handles `filesystem_condition_keyword ~ string ...`
                     let keyword = next_pair.as_str();
-                    let path = inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
+                    let path = string_content(inner.next().unwrap());
 
                     match keyword {
                         "file_exists" => Condition::FileExists { path },
@@ -973,14 +1282,7 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
                         "dir_exists" => Condition::DirExists { path },
                         "dir_does_not_exist" => Condition::DirDoesNotExist { path },
                         "file_contains" => {
-                            let content = inner
-                                .next()
-                                .unwrap()
-                                .into_inner()
-                                .next()
-                                .unwrap()
-                                .as_str()
-                                .to_string();
+                            let content = string_content(inner.next().unwrap());
                             Condition::FileContains { path, content }
                         }
                         _ => unreachable!("Unsupported filesystem condition keyword: {}", keyword),
@@ -991,56 +1293,62 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
         Rule::stdout_is_empty_condition => Condition::StdoutIsEmpty,
         Rule::stderr_is_empty_condition => Condition::StderrIsEmpty,
         Rule::stderr_contains_condition => {
-            let text = unescape_string(
-                inner_cond
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .as_str(),
-            );
+            let text = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
             Condition::StderrContains(text)
         }
+        Rule::stream_contains_condition => {
+            let mut inner = inner_cond.into_inner();
+            let stream = OutputStream::from_keyword(inner.next().unwrap().as_str());
+            let text = unescape_string(&string_content(inner.next().unwrap()));
+            Condition::StreamContains { stream, text }
+        }
+        Rule::stream_matches_condition => {
+            let mut inner = inner_cond.into_inner();
+            let stream = OutputStream::from_keyword(inner.next().unwrap().as_str());
+            let regex = string_content(inner.next().unwrap());
+            let capture_as = inner.next().map(|p| p.as_str().to_string());
+            Condition::StreamMatches {
+                stream,
+                regex,
+                capture_as,
+            }
+        }
+        Rule::stream_equals_condition => {
+            let mut inner = inner_cond.into_inner();
+            let stream = OutputStream::from_keyword(inner.next().unwrap().as_str());
+            let text = unescape_string(&string_content(inner.next().unwrap()));
+            Condition::StreamEquals { stream, text }
+        }
+        Rule::stream_line_count_condition => {
+            let mut inner = inner_cond.into_inner();
+            let stream = OutputStream::from_keyword(inner.next().unwrap().as_str());
+            let count = inner.next().unwrap().as_str().parse().unwrap();
+            Condition::StreamLineCount { stream, count }
+        }
+        Rule::stream_line_equals_condition => {
+            let mut inner = inner_cond.into_inner();
+            let stream = OutputStream::from_keyword(inner.next().unwrap().as_str());
+            let line = inner.next().unwrap().as_str().parse().unwrap();
+            let text = unescape_string(&string_content(inner.next().unwrap()));
+            Condition::StreamLineEquals { stream, line, text }
+        }
+        Rule::printed_before_condition => {
+            let mut inner = inner_cond.into_inner();
+            let first = unescape_string(&string_content(inner.next().unwrap()));
+            let second = unescape_string(&string_content(inner.next().unwrap()));
+            Condition::PrintedBefore { first, second }
+        }
         Rule::output_starts_with_condition => {
-            let text = unescape_string(
-                inner_cond
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .as_str(),
-            );
+            let text = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
             //println!("Building output_starts_with_condition '{}'", text);
             Condition::OutputStartsWith(text)
         }
         Rule::output_ends_with_condition => {
-            let text = unescape_string(
-                inner_cond
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .as_str(),
-            );
+            let text = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
             Condition::OutputEndsWith(text)
         }
         Rule::output_equals_condition => {
-            let text = unescape_string(
-                inner_cond
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .as_str(),
-            );
+            let text = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
             Condition::OutputEquals(text)
         }
         Rule::web_condition => {
@@ -1084,29 +1392,62 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
 
             Condition::ResponseTimeIsBelow { duration }
         }
+        Rule::response_body_matches_snapshot_condition => {
+            let name = string_content(inner_cond.into_inner().next().unwrap());
+            Condition::ResponseBodyMatchesSnapshot { name }
+        }
         Rule::response_body_contains_condition => {
-            let value = inner_cond
-                .into_inner()
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let value = string_content(inner_cond.into_inner().next().unwrap());
             Condition::ResponseBodyContains { value }
         }
         Rule::response_body_matches_condition => {
             let mut inner = inner_cond.into_inner();
-            let regex_str = inner.next().unwrap().into_inner().next().unwrap().as_str();
-            let regex = unescape_string(regex_str);
+            let regex_str = string_content(inner.next().unwrap());
+            let regex = unescape_string(&regex_str);
             let capture_as = inner.next().map(|p| p.as_str().to_string());
             Condition::ResponseBodyMatches { regex, capture_as }
         }
+        Rule::response_header_exists_condition => {
+            let name = string_content(inner_cond.into_inner().next().unwrap());
+            Condition::ResponseHeaderExists { name }
+        }
+        Rule::response_header_equals_condition => {
+            let mut inner = inner_cond.into_inner();
+            let name = string_content(inner.next().unwrap());
+            let value = string_content(inner.next().unwrap());
+            Condition::ResponseHeaderEquals { name, value }
+        }
+        Rule::response_header_contains_condition => {
+            let mut inner = inner_cond.into_inner();
+            let name = string_content(inner.next().unwrap());
+            let value = string_content(inner.next().unwrap());
+            Condition::ResponseHeaderContains { name, value }
+        }
+        Rule::response_header_matches_condition => {
+            let mut inner = inner_cond.into_inner();
+            let name = string_content(inner.next().unwrap());
+            let regex = unescape_string(&string_content(inner.next().unwrap()));
+            let capture_as = inner.next().map(|p| p.as_str().to_string());
+            Condition::ResponseHeaderMatches {
+                name,
+                regex,
+                capture_as,
+            }
+        }
+        Rule::cookie_exists_condition => {
+            let name = string_content(inner_cond.into_inner().next().unwrap());
+            Condition::CookieExists { name }
+        }
+        Rule::cookie_equals_condition => {
+            let mut inner = inner_cond.into_inner();
+            let name = string_content(inner.next().unwrap());
+            let value = string_content(inner.next().unwrap());
+            Condition::CookieEquals { name, value }
+        }
         Rule::response_body_equals_json => {
             let mut inner = inner_cond.into_inner();
             //println!("[Parser] response_body_equals_json inner: {:?}", inner);
-            let expected = inner.next().unwrap().as_str().trim_matches('"').to_string();
+            let expected = string_content(inner.next().unwrap());
             let mut ignored = Vec::new();
 
             if let Some(ignored_pair) = inner.next() {
@@ -1124,105 +1465,50 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
             Condition::ResponseBodyEqualsJson { expected, ignored }
         }
         Rule::json_value_is_string_condition => {
-            let path = inner_cond
-                .into_inner()
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner_cond.into_inner().next().unwrap());
             Condition::JsonValueIsString { path }
         }
         Rule::json_value_is_number_condition => {
-            let path = inner_cond
-                .into_inner()
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner_cond.into_inner().next().unwrap());
             Condition::JsonValueIsNumber { path }
         }
         Rule::json_value_is_array_condition => {
-            let path = inner_cond
-                .into_inner()
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner_cond.into_inner().next().unwrap());
             Condition::JsonValueIsArray { path }
         }
         Rule::json_value_is_object_condition => {
-            let path = inner_cond
-                .into_inner()
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner_cond.into_inner().next().unwrap());
             Condition::JsonValueIsObject { path }
         }
         Rule::json_value_has_size_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner.next().unwrap());
             let size_str = inner.next().unwrap().as_str();
             let size: usize = size_str.parse().unwrap();
             Condition::JsonValueHasSize { path, size }
         }
         Rule::json_body_has_path_condition => {
-            let path = inner_cond
-                .into_inner()
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner_cond.into_inner().next().unwrap());
             Condition::JsonBodyHasPath { path }
         }
         Rule::json_path_equals_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner.next().unwrap());
             let expected_value = build_value(inner.next().unwrap());
             Condition::JsonPathEquals {
                 path,
                 expected_value,
             }
         }
+        Rule::json_path_compare_condition => {
+            let mut inner = inner_cond.into_inner();
+            let path = string_content(inner.next().unwrap());
+            let comparison = build_json_comparison(inner.next().unwrap());
+            Condition::JsonPathCompare { path, comparison }
+        }
         Rule::json_path_capture_condition => {
             let mut inner = inner_cond.into_inner();
-            let path = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .unwrap()
-                .as_str()
-                .to_string();
+            let path = string_content(inner.next().unwrap());
             let capture_as = inner.next().map(|p| p.as_str().to_string()).unwrap();
             Condition::JsonPathCapture { path, capture_as }
         }
@@ -1232,42 +1518,15 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
             build_condition_from_specific(inner)
         }
         Rule::service_is_running_condition => {
-            let name = unescape_string(
-                inner_cond
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .as_str(),
-            );
+            let name = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
             Condition::ServiceIsRunning { name }
         }
         Rule::service_is_stopped_condition => {
-            let name = unescape_string(
-                inner_cond
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .as_str(),
-            );
+            let name = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
             Condition::ServiceIsStopped { name }
         }
         Rule::service_is_installed_condition => {
-            let name = unescape_string(
-                inner_cond
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .into_inner()
-                    .next()
-                    .unwrap()
-                    .as_str(),
-            );
+            let name = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
             Condition::ServiceIsInstalled { name }
         }
         Rule::port_is_listening_condition => {
@@ -1290,6 +1549,41 @@ pub fn build_condition_from_specific(inner_cond: Pair<Rule>) -> Condition {
                 .unwrap();
             Condition::PortIsClosed { port }
         }
+        // --- Mock Conditions ---
+        Rule::mock_condition => {
+            let inner = inner_cond.into_inner().next().unwrap();
+            build_condition_from_specific(inner)
+        }
+        Rule::mock_received_condition => {
+            let mut inner = inner_cond.into_inner();
+            let method = inner.next().unwrap().as_str().to_string();
+            let path = string_content(inner.next().unwrap());
+            let times = inner.next().map(|p| p.as_str().parse().unwrap());
+            Condition::MockReceived {
+                method,
+                path,
+                times,
+            }
+        }
+        Rule::mock_last_request_body_contains_condition => {
+            let text = unescape_string(&string_content(inner_cond.into_inner().next().unwrap()));
+            Condition::MockLastRequestBodyContains { text }
+        }
+        Rule::mock_last_request_header_equals_condition => {
+            let mut inner = inner_cond.into_inner();
+            let name = string_content(inner.next().unwrap());
+            let value = string_content(inner.next().unwrap());
+            Condition::MockLastRequestHeaderEquals { name, value }
+        }
+        Rule::mock_last_request_json_path_equals_condition => {
+            let mut inner = inner_cond.into_inner();
+            let path = string_content(inner.next().unwrap());
+            let expected_value = build_value(inner.next().unwrap());
+            Condition::MockLastRequestJsonPathEquals {
+                path,
+                expected_value,
+            }
+        }
         _ => unreachable!("Unhandled condition: {:?}", inner_cond.as_rule()),
     }
 }
@@ -1314,20 +1608,109 @@ pub fn build_action(inner_action: Pair<Rule>) -> Action {
         // --- Terminal Action ---
         Rule::run_action => {
             let mut inner = inner_action.into_inner();
-            //let _terminal = inner.next().unwrap(); // Skip "Terminal" keyword
-            //let _run = inner.next().unwrap(); // Skip "run" keyword
-            let command = inner.next().unwrap().into_inner().next().unwrap().as_str();
-            let command = unescape_string(command);
+            let actor = inner.next().unwrap().as_str().to_string();
+            let command = string_content(inner.next().unwrap());
+            let command = unescape_string(&command);
+            let mut timeout = None;
+            let mut env = Vec::new();
+            let mut stdin = None;
+            let mut capture_as = None;
+            for option in inner {
+                let kind = option.as_rule();
+                let value = option.into_inner().next().unwrap();
+                match kind {
+                    Rule::run_within => timeout = Some(parse_duration(value.as_str())),
+                    Rule::run_with_env => {
+                        if let Value::Object(map) = build_value(value) {
+                            env = object_pairs(map);
+                        }
+                    }
+                    Rule::run_with_stdin => {
+                        stdin = Some(StdinSource::Text(unescape_string(&string_content(value))))
+                    }
+                    Rule::run_with_stdin_file => {
+                        stdin = Some(StdinSource::File(string_content(value)))
+                    }
+                    Rule::run_capture => capture_as = Some(value.as_str().to_string()),
+                    _ => unreachable!("Unexpected run option: {:?}", kind),
+                }
+            }
             Action::Run {
-                actor: "Terminal".to_string(),
+                actor,
                 command,
+                timeout,
+                env,
+                stdin,
+                capture_as,
+            }
+        }
+        Rule::capture_action => {
+            let mut inner = inner_action.into_inner();
+            let actor = inner.next().unwrap().as_str().to_string();
+            let source = match inner.next().unwrap().as_str() {
+                "stdout" => CaptureSource::Stdout,
+                "stderr" => CaptureSource::Stderr,
+                _ => CaptureSource::ExitCode,
+            };
+            let variable = inner.next().unwrap().as_str().to_string();
+            Action::Capture {
+                actor,
+                source,
+                variable,
             }
         }
         Rule::set_cwd_action => {
             let mut inner = inner_action.into_inner();
-            let path = inner.next().unwrap().into_inner().next().unwrap().as_str();
-            let path = unescape_string(path);
-            Action::SetCwd { path }
+            let actor = inner.next().unwrap().as_str().to_string();
+            let path = string_content(inner.next().unwrap());
+            let path = unescape_string(&path);
+            Action::SetCwd { actor, path }
+        }
+        Rule::types_action => {
+            let mut inner = inner_action.into_inner();
+            let actor = inner.next().unwrap().as_str().to_string();
+            let text = unescape_string(&string_content(inner.next().unwrap()));
+            Action::Type { actor, text }
+        }
+        Rule::presses_action => {
+            let mut inner = inner_action.into_inner();
+            let actor = inner.next().unwrap().as_str().to_string();
+            let key = string_content(inner.next().unwrap());
+            Action::Press { actor, key }
+        }
+        Rule::spawn_action => {
+            let mut inner = inner_action.into_inner();
+            let actor = inner.next().unwrap().as_str().to_string();
+            let command = unescape_string(&string_content(inner.next().unwrap()));
+            Action::Spawn { actor, command }
+        }
+        Rule::start_action => {
+            let mut inner = inner_action.into_inner();
+            let actor = inner.next().unwrap().as_str().to_string();
+            let command = unescape_string(&string_content(inner.next().unwrap()));
+            let name = inner.next().unwrap().as_str().to_string();
+            Action::Start {
+                actor,
+                command,
+                name,
+            }
+        }
+        Rule::stop_action => {
+            let mut inner = inner_action.into_inner();
+            let actor = inner.next().unwrap().as_str().to_string();
+            let name = inner.next().unwrap().as_str().to_string();
+            Action::Stop { actor, name }
+        }
+        Rule::send_signal_action => {
+            let mut inner = inner_action.into_inner();
+            let actor = inner.next().unwrap().as_str().to_string();
+            let signal = string_content(inner.next().unwrap());
+            let name = inner.next().unwrap().as_str().to_string();
+            Action::SendSignal {
+                actor,
+                signal,
+                name,
+            }
         }
         // --- System Actions ---
         Rule::system_action => {
@@ -1342,8 +1725,7 @@ pub fn build_action(inner_action: Pair<Rule>) -> Action {
                     let mut action_inner = specific.into_inner();
                     // the only inner item is the string pair
                     let message_pair = action_inner.next().unwrap();
-                    let message =
-                        unescape_string(message_pair.into_inner().next().unwrap().as_str());
+                    let message = unescape_string(&string_content(message_pair));
                     Action::Log { message }
                 }
                 Rule::system_pause => {
@@ -1358,7 +1740,7 @@ pub fn build_action(inner_action: Pair<Rule>) -> Action {
                     // the only inner item is either a string or an identifier
                     let var_pair = action_inner.next().unwrap();
                     let var_name = if var_pair.as_rule() == Rule::string {
-                        unescape_string(var_pair.into_inner().next().unwrap().as_str())
+                        unescape_string(&string_content(var_pair))
                     } else {
                         var_pair.as_str().to_string()
                     };
@@ -1373,30 +1755,27 @@ pub fn build_action(inner_action: Pair<Rule>) -> Action {
                 _ => unreachable!("Unhandled system_action type: {:?}", specific.as_str()),
             }
         }
+        Rule::copy_dir_action => {
+            let mut inner = inner_action.into_inner();
+            let source = string_content(inner.next().unwrap());
+            let destination = string_content(inner.next().unwrap());
+            Action::CopyDir {
+                source,
+                destination,
+            }
+        }
         Rule::filesystem_action => {
             let mut inner = inner_action.into_inner();
             //let _actor = inner.next().unwrap().as_str(); // Consume the actor identifier
             let keyword = inner.next().unwrap().as_str();
-            let path = inner
-                .next()
-                .unwrap()
-                .into_inner()
-                .next()
-                .map_or(String::new(), |p| p.as_str().to_string());
+            let path = string_content(inner.next().unwrap());
 
             match keyword {
                 "create_dir" => Action::CreateDir { path },
                 "delete_file" => Action::DeleteFile { path },
                 "delete_dir" => Action::DeleteDir { path },
                 "create_file" => {
-                    let content = if let Some(content_pair) = inner.next() {
-                        content_pair
-                            .into_inner()
-                            .next()
-                            .map_or(String::new(), |p| p.as_str().to_string())
-                    } else {
-                        String::new()
-                    };
+                    let content = inner.next().map_or(String::new(), string_content);
                     Action::CreateFile { path, content }
                 }
                 "read_file" => {
@@ -1421,157 +1800,169 @@ pub fn build_action(inner_action: Pair<Rule>) -> Action {
 
             match method {
                 "set_header" => {
-                    let key = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
-                    let value = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
+                    let key = string_content(action_inner.next().unwrap());
+                    let value = string_content(action_inner.next().unwrap());
                     Action::HttpSetHeader { key, value }
                 }
                 "clear_header" => {
-                    let key = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
+                    let key = string_content(action_inner.next().unwrap());
                     Action::HttpClearHeader { key }
                 }
                 "clear_headers" => Action::HttpClearHeaders,
                 "set_cookie" => {
-                    let key = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
-                    let value = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
+                    let key = string_content(action_inner.next().unwrap());
+                    let value = string_content(action_inner.next().unwrap());
                     Action::HttpSetCookie { key, value }
                 }
                 "clear_cookie" => {
-                    let key = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
+                    let key = string_content(action_inner.next().unwrap());
                     Action::HttpClearCookie { key }
                 }
                 "clear_cookies" => Action::HttpClearCookies,
                 "http_get" => {
-                    let url = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
-                    Action::HttpGet { url }
+                    let url = string_content(action_inner.next().unwrap());
+                    let mut rest = action_inner.peekable();
+                    let options = http_options(iter::from_fn(|| {
+                        rest.next_if(|pair| pair.as_rule() != Rule::http_poll)
+                    }));
+                    let until = rest.next().map(|poll| {
+                        let mut inner = poll.into_inner();
+                        let condition = build_condition_from_specific(inner.next().unwrap());
+                        let (within, every) = poll_timing(inner);
+                        Box::new(HttpPoll {
+                            condition,
+                            within,
+                            every,
+                        })
+                    });
+                    Action::HttpGet {
+                        url,
+                        options,
+                        until,
+                    }
                 }
                 "http_post" => {
-                    let url = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
-                    let body = unescape_string(
-                        action_inner
-                            .next()
-                            .unwrap()
-                            .into_inner()
-                            .next()
-                            .unwrap()
-                            .as_str(),
-                    );
-                    Action::HttpPost { url, body }
+                    let url = string_content(action_inner.next().unwrap());
+                    let body = unescape_string(&string_content(action_inner.next().unwrap()));
+                    let options = http_options(action_inner);
+                    Action::HttpPost { url, body, options }
                 }
                 "http_put" => {
-                    let url = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
-                    let body = unescape_string(
-                        action_inner
-                            .next()
-                            .unwrap()
-                            .into_inner()
-                            .next()
-                            .unwrap()
-                            .as_str(),
-                    );
-                    Action::HttpPut { url, body }
+                    let url = string_content(action_inner.next().unwrap());
+                    let body = unescape_string(&string_content(action_inner.next().unwrap()));
+                    let options = http_options(action_inner);
+                    Action::HttpPut { url, body, options }
                 }
                 "http_patch" => {
-                    let url = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
-                    let body = unescape_string(
-                        action_inner
-                            .next()
-                            .unwrap()
-                            .into_inner()
-                            .next()
-                            .unwrap()
-                            .as_str(),
-                    );
-                    Action::HttpPatch { url, body }
+                    let url = string_content(action_inner.next().unwrap());
+                    let body = unescape_string(&string_content(action_inner.next().unwrap()));
+                    let options = http_options(action_inner);
+                    Action::HttpPatch { url, body, options }
                 }
                 "http_delete" => {
-                    let url = action_inner
-                        .next()
-                        .unwrap()
-                        .into_inner()
-                        .next()
-                        .unwrap()
-                        .as_str()
-                        .to_string();
-                    Action::HttpDelete { url }
+                    let url = string_content(action_inner.next().unwrap());
+                    let options = http_options(action_inner);
+                    Action::HttpDelete { url, options }
                 }
                 // ... other methods
                 _ => panic!("Unknown action method: {}", method),
             }
         }
+        Rule::eventually_action => {
+            let inner = inner_action.into_inner();
+            let (actions, timing): (Vec<_>, Vec<_>) =
+                inner.partition(|pair| pair.as_rule() == Rule::action);
+            let actions = actions
+                .into_iter()
+                .map(|pair| build_action(pair.into_inner().next().unwrap()))
+                .collect();
+            let (within, every) = poll_timing(timing.into_iter());
+            Action::Eventually {
+                actions,
+                within,
+                every,
+            }
+        }
+        // --- Mock Actions ---
+        Rule::mock_serve_action => {
+            let mut inner = inner_action.into_inner();
+            let port = inner.next().unwrap().as_str().parse().unwrap();
+            let routes = inner.map(build_mock_route).collect();
+            Action::MockServe { port, routes }
+        }
         _ => unreachable!("Unhandled action: {:?}", inner_action.as_rule()),
     }
 }
 
+/// Builds one route of a `Mock serve` block.
+fn build_mock_route(pair: Pair<Rule>) -> MockRoute {
+    let mut inner = pair.into_inner();
+    let method = inner.next().unwrap().as_str().to_string();
+    let path = string_content(inner.next().unwrap());
+    let response_pair = inner.next().unwrap();
+    let response = match response_pair.as_rule() {
+        Rule::mock_responds => {
+            let mut parts = response_pair.into_inner();
+            let status = parts.next().unwrap().as_str().parse().unwrap();
+            let mut body = String::new();
+            let mut headers = Vec::new();
+            for part in parts {
+                let kind = part.as_rule();
+                let mut values = part.into_inner();
+                if kind == Rule::mock_body {
+                    body = unescape_string(&string_content(values.next().unwrap()));
+                } else {
+                    let key = string_content(values.next().unwrap());
+                    let value = string_content(values.next().unwrap());
+                    headers.push((key, value));
+                }
+            }
+            MockResponse::Respond {
+                status,
+                body,
+                headers,
+            }
+        }
+        _ => MockResponse::CloseConnection,
+    };
+
+    let mut route = MockRoute {
+        method,
+        path,
+        response,
+        delay: 0.0,
+        times: None,
+    };
+    for option in inner {
+        let kind = option.as_rule();
+        let value = option.into_inner().next().unwrap().as_str();
+        if kind == Rule::mock_delay {
+            route.delay = parse_duration(value);
+        } else {
+            route.times = Some(value.parse().unwrap());
+        }
+    }
+    route
+}
+
+fn build_json_comparison(pair: Pair<Rule>) -> JsonComparison {
+    let comparison = pair.into_inner().next().unwrap();
+    let kind = comparison.as_rule();
+    let mut inner = comparison.into_inner();
+    match kind {
+        Rule::json_greater_than => JsonComparison::GreaterThan(build_value(inner.next().unwrap())),
+        Rule::json_less_than => JsonComparison::LessThan(build_value(inner.next().unwrap())),
+        Rule::json_between => JsonComparison::Between(
+            build_value(inner.next().unwrap()),
+            build_value(inner.next().unwrap()),
+        ),
+        Rule::json_not_equals => JsonComparison::NotEquals(build_value(inner.next().unwrap())),
+        Rule::json_matches => JsonComparison::Matches(string_content(inner.next().unwrap())),
+        Rule::json_is_null => JsonComparison::IsNull,
+        _ => unreachable!("Unhandled JSON comparison: {:?}", kind),
+    }
+}
+
 fn build_value(pair: Pair<Rule>) -> Value {
     // The `value` rule is silent, so we need to inspect its inner pair.
     //let inner_pair = pair.clone().into_inner().next().unwrap();
@@ -1581,11 +1972,14 @@ fn build_value(pair: Pair<Rule>) -> Value {
     };
     //println!("{:?}", inner_pair);
     match inner_pair.as_rule() {
-        Rule::string => {
-            let inner = inner_pair.into_inner().next().unwrap();
-            Value::String(unescape_string(inner.as_str()))
-        }
-        Rule::number => Value::Number(inner_pair.as_str().parse().unwrap()),
+        Rule::string => Value::String(unescape_string(&string_content(inner_pair))),
+        // Integers that do not fit in an i64 are kept as floats rather than rejected.
+        Rule::integer => match inner_pair.as_str().parse() {
+            Ok(n) => Value::Number(n),
+            Err(_) => Value::Float(inner_pair.as_str().parse().unwrap()),
+        },
+        Rule::decimal => Value::Float(inner_pair.as_str().parse().unwrap()),
+        Rule::null => Value::Null,
         Rule::identifier => {
             // Handle variable references - convert identifier to a placeholder string
             let var_name = pair.as_str();
@@ -1611,7 +2005,7 @@ fn build_value(pair: Pair<Rule>) -> Value {
                 let value_pair = kv.next().unwrap();
 
                 let key = if key_pair.as_rule() == Rule::string {
-                    unescape_string(key_pair.into_inner().next().unwrap().as_str())
+                    unescape_string(&string_content(key_pair))
                 } else {
                     key_pair.as_str().to_string()
                 };
@@ -1632,6 +2026,56 @@ fn build_value(pair: Pair<Rule>) -> Value {
     }
 }
 
+/// Turns an object such as `{ FOO: "bar", PORT: 8080 }` into name/value pairs, e.g. environment
+/// variables or query parameters, sorted by name.
+fn object_pairs(map: HashMap<String, Value>) -> Vec<(String, String)> {
+    let mut pairs: Vec<(String, String)> = map
+        .into_iter()
+        .map(|(key, value)| (key, value.as_string()))
+        .collect();
+    pairs.sort();
+    pairs
+}
+
+/// Reads the options of a web request, e.g. `with_query { page: 2 } timeout 2s`.
+fn http_options<'a>(pairs: impl Iterator<Item = Pair<'a, Rule>>) -> HttpOptions {
+    let mut options = HttpOptions::default();
+    for option in pairs {
+        let kind = option.as_rule();
+        let value = option.into_inner().next().unwrap();
+        match kind {
+            Rule::http_with_query => {
+                if let Value::Object(map) = build_value(value) {
+                    options.query = object_pairs(map);
+                }
+            }
+            Rule::http_with_headers => {
+                if let Value::Object(map) = build_value(value) {
+                    options.headers = object_pairs(map);
+                }
+            }
+            Rule::http_timeout => options.timeout = Some(parse_duration(value.as_str())),
+            Rule::http_follow_redirects => {
+                options.follow_redirects = Some(value.as_str().parse().unwrap())
+            }
+            Rule::http_max_redirects => {
+                options.max_redirects = Some(value.as_str().parse().unwrap())
+            }
+            _ => unreachable!("Unexpected web request option: {:?}", kind),
+        }
+    }
+    options
+}
+
+/// Reads `within <duration> [every <duration>]`, polling every 500ms by default.
+fn poll_timing<'a>(mut pairs: impl Iterator<Item = Pair<'a, Rule>>) -> (f32, f32) {
+    let within = parse_duration(pairs.next().unwrap().as_str());
+    let every = pairs
+        .next()
+        .map_or(0.5, |pair| parse_duration(pair.as_str()));
+    (within, every)
+}
+
 fn parse_duration(duration_str: &str) -> f32 {
     if duration_str.ends_with("ms") {
         let num_part = &duration_str[..duration_str.len() - 2];
@@ -1644,6 +2088,42 @@ fn parse_duration(duration_str: &str) -> f32 {
     }
 }
 
+/// Returns the text of a `string` or `non_empty_string` pair without its quotes.
+/// Triple-quoted strings are dedented, see [`dedent`].
+fn string_content(pair: Pair<Rule>) -> String {
+    match pair.into_inner().next() {
+        Some(inner) if inner.as_rule() == Rule::within_triple => dedent(inner.as_str()),
+        Some(inner) => inner.as_str().to_string(),
+        None => String::new(),
+    }
+}
+
+/// Normalises the body of a triple-quoted string: the line break after the opening quotes and
+/// the whitespace-only line before the closing quotes are dropped, and the indentation common
+/// to all non-blank lines is removed.
+pub fn dedent(s: &str) -> String {
+    let mut lines: Vec<&str> = s.lines().collect();
+    if lines.first().is_some_and(|l| l.trim().is_empty()) {
+        lines.remove(0);
+    }
+    if lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|l| l.get(indent..).unwrap_or_else(|| l.trim_start()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// Unescapes a string captured by the parser.
 pub fn unescape_string(s: &str) -> String {
     s.replace("\\\"", "\"")
@@ -1,44 +1,147 @@
 use crate::backend::report::{
-    AfterHook, Feature, Report, Result as StepResult, Scenario as ReportScenario, Step, Summary,
+    AfterHook, Feature, JunitFailure, JunitReport, JunitSkipped, Report, Result as StepResult,
+    Scenario as ReportScenario, Step, Summary, TestCaseReport, TestStatus, TestSuiteReport,
 };
 use crate::colours;
 use crate::error::AppError;
-use crate::parser::ast::{Action, ReportFormat, Scenario, TestState, TestSuiteSettings};
+use crate::parser::ast::{Action, ReportFormat, TestState, TestSuiteSettings};
 use crate::parser::helpers::substitute_variables_in_action;
-use std::collections::HashMap;
+use crate::runner::SuiteResult;
+use serde::Serialize;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
+/// Where and in which format a run's report is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportTarget {
+    pub format: ReportFormat,
+    pub path: String,
+}
+
+impl ReportTarget {
+    /// Picks the report format and path for a run. Values given on the command line win;
+    /// otherwise all suites have to agree on their `report_format` and `report_path` settings.
+    pub fn resolve(
+        suites: &[(&str, TestSuiteSettings)],
+        format: Option<ReportFormat>,
+        path: Option<String>,
+    ) -> Result<Self, AppError> {
+        let defaults = TestSuiteSettings::default();
+        let (first_name, first) = suites
+            .first()
+            .map(|(name, s)| (*name, s))
+            .unwrap_or(("", &defaults));
+
+        let format = match format {
+            Some(format) => format,
+            None => {
+                if let Some((name, s)) = suites
+                    .iter()
+                    .find(|(_, s)| s.report_format != first.report_format)
+                {
+                    return Err(AppError::ReportConflict(format!(
+                        "'{}' uses report_format '{}' but '{}' uses '{}'; choose one with --report-format",
+                        first_name,
+                        format_name(&first.report_format),
+                        name,
+                        format_name(&s.report_format)
+                    )));
+                }
+                first.report_format.clone()
+            }
+        };
+        let path = match path {
+            Some(path) => path,
+            None => {
+                if let Some((name, s)) = suites
+                    .iter()
+                    .find(|(_, s)| s.report_path != first.report_path)
+                {
+                    return Err(AppError::ReportConflict(format!(
+                        "'{}' uses report_path '{}' but '{}' uses '{}'; choose one with --report-path",
+                        first_name, first.report_path, name, s.report_path
+                    )));
+                }
+                first.report_path.clone()
+            }
+        };
+
+        Ok(Self { format, path })
+    }
+}
+
+/// The name of a report format as written in a suite's settings.
+fn format_name(format: &ReportFormat) -> &'static str {
+    match format {
+        ReportFormat::Json => "json",
+        ReportFormat::Junit => "junit",
+        ReportFormat::None => "none",
+    }
+}
+
+/// A suite that could not be run, e.g. because it failed to parse.
+#[derive(Debug, Clone)]
+pub struct SuiteError {
+    pub suite_name: String,
+    pub message: String,
+}
+
+/// Writes a single report covering all given suites, including those that could not be run.
 pub fn generate_choreo_report(
-    suite_name: &str,
-    suite_duration: Duration,
-    feature_name: &str,
-    scenarios: &[Scenario],
-    test_states: &HashMap<String, TestState>,
-    test_start_times: &HashMap<String, Instant>,
-    env_vars: &HashMap<String, String>,
-    settings: &TestSuiteSettings,
+    results: &[SuiteResult],
+    errors: &[SuiteError],
+    target: &ReportTarget,
     verbose: bool,
 ) -> Result<(), AppError> {
+    if results.is_empty() && errors.is_empty() {
+        return Ok(());
+    }
+
+    match target.format {
+        ReportFormat::None => return Ok(()),
+        ReportFormat::Junit => {
+            let junit = build_junit_report(results, errors);
+            write_junit_report(&junit, target, verbose)?;
+        }
+        ReportFormat::Json => {
+            let features = results
+                .iter()
+                .map(build_feature)
+                .chain(errors.iter().map(build_errored_feature));
+            let report = Report(features.collect());
+            write_json_report(&report, target, verbose)?;
+        }
+    }
+
+    if verbose {
+        colours::success("Reports generated successfully.");
+    }
+    Ok(())
+}
+
+/// Builds the JSON report feature for one suite.
+fn build_feature(result: &SuiteResult) -> Feature {
     let mut report_scenarios = Vec::new();
 
-    for scenario in scenarios {
+    for scenario in &result.scenarios {
         let mut steps = Vec::new();
         let mut after_hooks = Vec::new();
 
         for tc in scenario.tests.iter() {
             let scoped_key = format!("{}::{}", scenario.name, tc.name);
-            let (status, error_message) = match test_states.get(&scoped_key) {
+            let (status, error_message) = match result.test_states.get(&scoped_key) {
                 Some(TestState::Passed) => ("passed".to_string(), None),
                 Some(TestState::Failed(reason)) => ("failed".to_string(), Some(reason.clone())),
                 _ => ("skipped".to_string(), None),
             };
 
-            let duration = test_start_times
+            let duration = result
+                .test_durations
                 .get(&scoped_key)
-                .map_or(Duration::default(), |s| s.elapsed());
+                .copied()
+                .unwrap_or_default();
 
             steps.push(Step {
                 name: tc.name.clone(),
@@ -54,7 +157,8 @@ pub fn generate_choreo_report(
         for step in &scenario.after {
             let name = match step {
                 crate::parser::ast::WhenStep::Action(action) => {
-                    let substituted_action = substitute_variables_in_action(action, env_vars);
+                    let substituted_action =
+                        substitute_variables_in_action(action, &result.env_vars);
                     format_action_for_report(&substituted_action)
                 }
                 crate::parser::ast::WhenStep::TaskCall(tc) => {
@@ -79,29 +183,45 @@ pub fn generate_choreo_report(
         });
     }
 
-    if settings.report_format == ReportFormat::Junit {
-        if verbose {
-            colours::warn("JUnit report format is not yet supported. Skipping report generation.");
-        }
-        return Ok(());
+    Feature {
+        uri: result.suite_name.clone(),
+        keyword: "Feature".to_string(),
+        name: result.feature_name.clone(),
+        elements: report_scenarios,
+        summary: Summary {
+            tests: result.test_states.len(),
+            failures: result.failed(),
+            total_time_in_seconds: result.duration.as_secs_f32(),
+        },
+        error: None,
     }
+}
 
-    let report = Report(vec![Feature {
-        uri: suite_name.to_string(),
+/// Builds the JSON report feature for a suite that could not be run.
+fn build_errored_feature(error: &SuiteError) -> Feature {
+    Feature {
+        uri: error.suite_name.clone(),
         keyword: "Feature".to_string(),
-        name: feature_name.to_string(),
-        elements: report_scenarios,
+        name: error.suite_name.clone(),
+        elements: Vec::new(),
         summary: Summary {
-            tests: test_states.len(),
-            failures: test_states.values().filter(|s| s.is_failed()).count(),
-            total_time_in_seconds: suite_duration.as_secs_f32(),
+            tests: 0,
+            failures: 0,
+            total_time_in_seconds: 0.0,
         },
-    }]);
+        error: Some(error.message.clone()),
+    }
+}
 
-    let json = serde_json::to_string_pretty(&report)?;
+fn write_json_report(
+    report: &Report,
+    target: &ReportTarget,
+    verbose: bool,
+) -> Result<(), AppError> {
+    let json = serde_json::to_string_pretty(report)?;
     let date = chrono::Local::now().format("%Y%m%d_%H%M%S");
-    fs::create_dir_all(&settings.report_path)?;
-    let report_file_path = format!("{}choreo_test_report_{}.json", settings.report_path, date);
+    fs::create_dir_all(&target.path)?;
+    let report_file_path = format!("{}choreo_test_report_{}.json", target.path, date);
     let mut json_file = File::create(&report_file_path)?;
     json_file.write_all(json.as_bytes())?;
 
@@ -113,10 +233,170 @@ pub fn generate_choreo_report(
     Ok(())
 }
 
+/// Builds a JUnit report with one `<testsuite>` per scenario across all suites,
+/// plus an errored `<testsuite>` for each suite that could not be run.
+fn build_junit_report(results: &[SuiteResult], errors: &[SuiteError]) -> JunitReport {
+    let mut testsuites = Vec::new();
+
+    for result in results {
+        for scenario in &result.scenarios {
+            let mut testcases = Vec::new();
+
+            for tc in &scenario.tests {
+                let scoped_key = format!("{}::{}", scenario.name, tc.name);
+                let time = result
+                    .test_durations
+                    .get(&scoped_key)
+                    .copied()
+                    .unwrap_or_default();
+                let (status, failure, skipped) = match result.test_states.get(&scoped_key) {
+                    Some(TestState::Passed) => (TestStatus::Passed, None, None),
+                    Some(TestState::Failed(reason)) => (
+                        TestStatus::Failed,
+                        Some(JunitFailure {
+                            message: reason.clone(),
+                            kind: "AssertionError".to_string(),
+                            text: reason.clone(),
+                        }),
+                        None,
+                    ),
+                    _ => (
+                        TestStatus::Skipped,
+                        None,
+                        Some(JunitSkipped { message: None }),
+                    ),
+                };
+                let output = result.test_outputs.get(&scoped_key);
+                let non_empty = |s: &str| {
+                    let s = strip_ansi_escapes::strip_str(s);
+                    (!s.trim().is_empty()).then_some(s)
+                };
+
+                testcases.push(TestCaseReport {
+                    name: tc.name.clone(),
+                    classname: format!("{}.{}", result.feature_name, scenario.name),
+                    file: result.suite_name.clone(),
+                    time,
+                    status,
+                    failure,
+                    error: None,
+                    skipped,
+                    system_out: output.and_then(|o| non_empty(&o.stdout)),
+                    system_err: output.and_then(|o| non_empty(&o.stderr)),
+                });
+            }
+
+            testsuites.push(TestSuiteReport {
+                name: scenario.name.clone(),
+                tests: testcases.len(),
+                failures: testcases
+                    .iter()
+                    .filter(|t| t.status == TestStatus::Failed)
+                    .count(),
+                errors: 0,
+                skipped: testcases
+                    .iter()
+                    .filter(|t| t.status == TestStatus::Skipped)
+                    .count(),
+                time: testcases.iter().map(|t| t.time).sum(),
+                testcases,
+            });
+        }
+    }
+
+    for error in errors {
+        let failure = JunitFailure {
+            message: error.message.clone(),
+            kind: "SuiteError".to_string(),
+            text: error.message.clone(),
+        };
+        testsuites.push(TestSuiteReport {
+            name: error.suite_name.clone(),
+            tests: 1,
+            failures: 0,
+            errors: 1,
+            skipped: 0,
+            time: Duration::ZERO,
+            testcases: vec![TestCaseReport {
+                name: error.suite_name.clone(),
+                classname: error.suite_name.clone(),
+                file: error.suite_name.clone(),
+                time: Duration::ZERO,
+                status: TestStatus::Errored,
+                failure: None,
+                error: Some(failure),
+                skipped: None,
+                system_out: None,
+                system_err: None,
+            }],
+        });
+    }
+
+    let name = match (results, errors) {
+        ([single], []) => single.feature_name.clone(),
+        _ => "choreo".to_string(),
+    };
+
+    JunitReport {
+        name,
+        tests: testsuites.iter().map(|s| s.tests).sum(),
+        failures: testsuites.iter().map(|s| s.failures).sum(),
+        errors: testsuites.iter().map(|s| s.errors).sum(),
+        skipped: testsuites.iter().map(|s| s.skipped).sum(),
+        time: results.iter().map(|r| r.duration).sum::<Duration>(),
+        testsuites,
+    }
+}
+
+fn write_junit_report(
+    junit: &JunitReport,
+    target: &ReportTarget,
+    verbose: bool,
+) -> Result<(), AppError> {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let mut serializer = quick_xml::se::Serializer::new(&mut xml);
+    serializer.indent(' ', 2);
+    junit
+        .serialize(serializer)
+        .map_err(|e| AppError::ReportError(e.to_string()))?;
+    xml.push('\n');
+
+    let date = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    fs::create_dir_all(&target.path)?;
+    let report_file_path = format!("{}choreo_test_report_{}.xml", target.path, date);
+    fs::write(&report_file_path, &xml)?;
+
+    if verbose {
+        colours::info("JUnit report content:");
+        println!("{}", xml);
+    }
+
+    Ok(())
+}
+
 fn format_action_for_report(action: &Action) -> String {
     match action {
-        Action::Run { actor, command } => format!("{} runs '{}'", actor, command),
-        Action::SetCwd { path } => format!("Terminal set_cwd '{}'", path),
+        Action::Run { actor, command, .. } => format!("{} runs '{}'", actor, command),
+        Action::SetCwd { actor, path } => format!("{} set_cwd '{}'", actor, path),
+        Action::Type { actor, text } => format!("{} types '{}'", actor, text),
+        Action::Press { actor, key } => format!("{} presses '{}'", actor, key),
+        Action::Spawn { actor, command } => format!("{} spawns '{}'", actor, command),
+        Action::Start {
+            actor,
+            command,
+            name,
+        } => format!("{} starts '{}' as {}", actor, command, name),
+        Action::Stop { actor, name } => format!("{} stops {}", actor, name),
+        Action::SendSignal {
+            actor,
+            signal,
+            name,
+        } => format!("{} sends {} to {}", actor, signal, name),
+        Action::Capture {
+            actor,
+            source,
+            variable,
+        } => format!("{} captures {} as {}", actor, source, variable),
         Action::Pause { duration } => format!("duration of '{}'", duration),
         Action::Log { message } => format!("logs '{}'", message),
         Action::Timestamp { variable } => format!("timestamp at ({})", variable),
@@ -125,6 +405,24 @@ fn format_action_for_report(action: &Action) -> String {
         Action::DeleteFile { path } => format!("FileSystem delete_file '{}'", path),
         Action::CreateDir { path } => format!("FileSystem create_dir '{}'", path),
         Action::DeleteDir { path } => format!("FileSystem delete_dir '{}'", path),
+        Action::CopyDir {
+            source,
+            destination,
+        } => format!("FileSystem copy_dir '{}' to '{}'", source, destination),
+        Action::MockServe { port, routes } => {
+            format!("Mock serves {} route(s) on port {}", routes.len(), port)
+        }
+        Action::Eventually {
+            actions, within, ..
+        } => format!(
+            "Eventually within {}s: {}",
+            within,
+            actions
+                .iter()
+                .map(format_action_for_report)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
         Action::ReadFile { path, variable } => format!(
             "FileSystem read_file '{}' with variable: {:?}",
             path, variable
@@ -1,19 +1,27 @@
+use crate::backend::backends::Backends;
 use crate::backend::filesystem_backend::FileSystemBackend;
+use crate::backend::mock_backend::MockBackend;
+use crate::backend::report::CapturedOutput;
+use crate::backend::snapshot::SnapshotStore;
 use crate::backend::system_backend::SystemBackend;
-use crate::backend::terminal_backend::TerminalBackend;
+use crate::backend::terminal_backend::{TerminalBackend, TerminalSessions};
 use crate::backend::web_backend::WebBackend;
+use crate::backend::workspace::Workspace;
 use crate::colours;
 use crate::error::AppError;
+use crate::filter::TestFilter;
 use crate::parser::ast::{
-    Action, Condition, GivenStep, ReportFormat, Scenario, Statement, TaskArg, TaskBodyItem,
-    TaskCall, TaskDef, TestCase, TestState, TestSuite, TestSuiteSettings, ThenStep, WhenStep,
+    Action, Condition, GivenStep, ReportFormat, Scenario, ScenarioBodyItem, Statement, TaskArg,
+    TaskBodyItem, TaskCall, TaskDef, TestCase, TestState, TestSuite, TestSuiteSettings, ThenStep,
+    WhenStep,
 };
 use crate::parser::helpers::{
-    check_all_conditions_met, extract_conditions_from_then_steps, is_synchronous,
-    substitute_variables_in_action, substitute_variables_in_condition,
+    check_all_conditions_met, describe_condition_failures, extract_conditions_from_then_steps,
+    is_synchronous, snapshot_names, substitute_variables_in_action,
+    substitute_variables_in_condition,
 };
-use crate::parser::parser::expand_foreach_blocks;
-use crate::reporting::generate_choreo_report;
+use crate::parser::parser::{expand_foreach_blocks, expand_scenario_outline};
+use crate::reporting::{ReportTarget, generate_choreo_report};
 use rayon::prelude::*;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -21,6 +29,43 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
+/// The outcome of running a single `.chor` suite, used for summaries and reports.
+#[derive(Debug, Clone)]
+pub struct SuiteResult {
+    pub suite_name: String,
+    pub feature_name: String,
+    pub duration: Duration,
+    pub scenarios: Vec<Scenario>,
+    pub test_states: HashMap<String, TestState>,
+    pub test_durations: HashMap<String, Duration>,
+    pub test_outputs: HashMap<String, CapturedOutput>,
+    pub env_vars: HashMap<String, String>,
+    pub settings: TestSuiteSettings,
+}
+
+impl SuiteResult {
+    pub fn passed(&self) -> usize {
+        self.count(|s| matches!(s, TestState::Passed))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(TestState::is_failed)
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|s| matches!(s, TestState::Skipped))
+    }
+
+    /// Returns true if more tests failed than the suite's `expected_failures` allows.
+    pub fn has_unexpected_failures(&self) -> bool {
+        self.failed() > self.settings.expected_failures
+    }
+
+    fn count(&self, predicate: impl Fn(&TestState) -> bool) -> usize {
+        self.test_states.values().filter(|s| predicate(s)).count()
+    }
+}
+
 pub struct TestRunner {
     test_suite: TestSuite,
     base_dir: PathBuf,
@@ -28,6 +73,10 @@ pub struct TestRunner {
     #[allow(dead_code)] // Will be used for task expansion in future implementation
     tasks: HashMap<String, TaskDef>,
     verbose: bool,
+    filter: TestFilter,
+    update_snapshots: bool,
+    report_format: Option<ReportFormat>,
+    report_path: Option<String>,
 }
 
 impl TestRunner {
@@ -44,19 +93,82 @@ impl TestRunner {
             env_vars,
             tasks,
             verbose,
+            filter: TestFilter::default(),
+            update_snapshots: false,
+            report_format: None,
+            report_path: None,
         }
     }
 
+    /// Restricts the run to the tests selected by the filter; the rest are reported as skipped.
+    pub fn with_filter(mut self, filter: TestFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Rewrites snapshots that no longer match instead of failing the tests that check them.
+    pub fn with_update_snapshots(mut self, update: bool) -> Self {
+        self.update_snapshots = update;
+        self
+    }
+
+    /// Writes the report in this format and directory instead of the ones in the suite's settings.
+    pub fn with_report_overrides(
+        mut self,
+        format: Option<ReportFormat>,
+        path: Option<String>,
+    ) -> Self {
+        self.report_format = format;
+        self.report_path = path;
+        self
+    }
+
+    /// The suite's `settings` block, or the defaults if it has none.
+    pub fn settings(&self) -> TestSuiteSettings {
+        self.test_suite
+            .statements
+            .iter()
+            .rev()
+            .find_map(|s| match s {
+                Statement::SettingsDef(s_def) => Some(s_def.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Runs the suite, writes its report and fails if more tests failed than expected.
     pub fn run(&mut self, suite_name: &str, scenarios: &[Scenario]) -> Result<(), AppError> {
-        let mut settings = TestSuiteSettings::default();
+        let result = self.run_suite(suite_name, scenarios)?;
+        let target = ReportTarget::resolve(
+            &[(suite_name, result.settings.clone())],
+            self.report_format.clone(),
+            self.report_path.clone(),
+        )?;
+        generate_choreo_report(std::slice::from_ref(&result), &[], &target, self.verbose)?;
+
+        if result.has_unexpected_failures() {
+            return Err(AppError::TestsFailed {
+                count: result.failed(),
+                expected: result.settings.expected_failures,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Runs all scenarios of the suite and prints its summary, without writing a report.
+    pub fn run_suite(
+        &mut self,
+        suite_name: &str,
+        scenarios: &[Scenario],
+    ) -> Result<SuiteResult, AppError> {
+        let mut settings = self.settings();
         let mut feature_name = "Choreo Test Feature".to_string(); // Default name
         //let mut scenarios: Vec<crate::parser::ast::Scenario> = Vec::new();
 
         for s in &self.test_suite.statements {
-            match s {
-                Statement::SettingsDef(s_def) => settings = s_def.clone(),
-                Statement::FeatureDef(name) => feature_name = name.clone(),
-                _ => {}
+            if let Statement::FeatureDef(name) = s {
+                feature_name = name.clone();
             }
         }
 
@@ -66,8 +178,20 @@ impl TestRunner {
         }
 
         // Clone scenarios into a mutable Vec so we can remove Background and run it first.
-        let mut scenarios_vec: Vec<_> = scenarios.iter().cloned().collect();
-        let mut bg_http_headers: HashMap<String, String> = HashMap::new();
+        // Scenario outlines are expanded here, one scenario per examples row.
+        let mut scenarios_vec: Vec<_> = scenarios
+            .iter()
+            .map(expand_scenario_outline)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        let snapshots = SnapshotStore::new(
+            &self.base_dir,
+            self.update_snapshots,
+            &settings.snapshot_redactions,
+        );
+        let mut background = BackgroundState::default();
 
         // Run any Background scenario first so its actions (e.g. Web set_header) modify `self.env_vars`.
         // NOTE: Background runs exactly ONCE for the entire suite (not before each scenario).
@@ -80,10 +204,15 @@ impl TestRunner {
             let bg = scenarios_vec.remove(pos);
 
             // Per-background backends (run on main thread, mutating self.env_vars)
-            let mut terminal_backend =
-                TerminalBackend::new(self.base_dir.clone(), settings.clone());
-            let fs_backend = FileSystemBackend::new();
-            let mut web_backend = WebBackend::new();
+            let mut backends = Backends {
+                terminal: TerminalBackend::new(self.base_dir.clone(), settings.clone()),
+                sessions: TerminalSessions::new(self.base_dir.clone(), settings.clone()),
+                fs: FileSystemBackend::with_snapshots(snapshots.clone())
+                    .with_base_dir(self.base_dir.clone()),
+                web: WebBackend::new().with_settings(&settings),
+                mock: MockBackend::new(),
+                system: SystemBackend::new(),
+            };
             let mut last_exit_code: Option<i32> = None;
 
             // Background was created in main as a single test with given steps.
@@ -93,9 +222,7 @@ impl TestRunner {
                         // Use the runner method which mutates self.env_vars
                         self.execute_action(
                             action,
-                            &mut terminal_backend,
-                            &fs_backend,
-                            &mut web_backend,
+                            &mut backends,
                             &mut last_exit_code,
                             settings.timeout_seconds,
                         );
@@ -103,20 +230,38 @@ impl TestRunner {
                 }
             }
 
-            // Capture headers set by the background web backend so scenarios inherit them
-            bg_http_headers = web_backend.get_headers();
+            // Capture headers set by the background web backend so scenarios inherit them,
+            // and keep the mock servers it started running for every scenario.
+            background = BackgroundState {
+                http_headers: backends.web.get_headers(),
+                mock: backends.mock,
+            };
 
             if self.verbose {
                 colours::success("Background setup applied to runner env_vars.");
             }
         }
 
+        // Expand foreach blocks and test outlines up front, so every generated test is
+        // registered below and shows up in the report as its own test.
+        for scenario in &mut scenarios_vec {
+            scenario.tests = expand_foreach_blocks(scenario, &self.env_vars)?;
+            scenario.body = scenario
+                .tests
+                .iter()
+                .cloned()
+                .map(ScenarioBodyItem::Test)
+                .collect();
+        }
+
         // -- Parallel execution using Rayon --
         //let mut test_states: HashMap<String, TestState> = HashMap::new();
         //let mut test_start_times: HashMap<String, Instant> = HashMap::new();
 
         let test_states = Arc::new(Mutex::new(HashMap::new()));
         let test_start_times = Arc::new(Mutex::new(HashMap::new()));
+        let test_durations = Arc::new(Mutex::new(HashMap::new()));
+        let test_outputs = Arc::new(Mutex::new(HashMap::new()));
 
         // --- Main Test Loop ---
         let suite_start_time = Instant::now();
@@ -159,7 +304,11 @@ impl TestRunner {
                         &self.base_dir,
                         Arc::clone(&test_states),
                         Arc::clone(&test_start_times),
-                        bg_http_headers.clone(),
+                        Arc::clone(&test_durations),
+                        Arc::clone(&test_outputs),
+                        &background,
+                        &self.filter,
+                        &snapshots,
                     )
                 })
                 .collect();
@@ -189,75 +338,56 @@ impl TestRunner {
                     &self.base_dir,
                     Arc::clone(&test_states),
                     Arc::clone(&test_start_times),
-                    bg_http_headers.clone(),
+                    Arc::clone(&test_durations),
+                    Arc::clone(&test_outputs),
+                    &background,
+                    &self.filter,
+                    &snapshots,
                 )?;
             }
         }
 
-        // --- Final Reporting ---
-        let suite_duration = suite_start_time.elapsed();
-        // Snapshot final maps for reporting
-        let test_states_final = test_states.lock().unwrap().clone();
-        let test_start_times_final = test_start_times.lock().unwrap().clone();
-
-        // This is the logic from the old print_summary function
-        let mut passed = 0;
-        let mut failed = 0;
-        let mut skipped = 0;
-        for state in test_states_final.values() {
-            match state {
-                TestState::Passed => passed += 1,
-                TestState::Failed(_) => failed += 1,
-                TestState::Skipped => skipped += 1,
-                _ => {}
-            }
+        if !self.filter.is_empty()
+            && test_states
+                .lock()
+                .unwrap()
+                .values()
+                .all(|s| matches!(s, TestState::Skipped))
+        {
+            colours::warn("No tests matched the given filters.");
         }
+
+        // --- Final Summary ---
+        let result = SuiteResult {
+            suite_name: suite_name.to_string(),
+            feature_name,
+            duration: suite_start_time.elapsed(),
+            scenarios: scenarios_vec,
+            test_states: test_states.lock().unwrap().clone(),
+            test_durations: test_durations.lock().unwrap().clone(),
+            test_outputs: test_outputs.lock().unwrap().clone(),
+            env_vars: self.env_vars.clone(),
+            settings,
+        };
+
         colours::info(&format!(
             "\nTest suite '{}' summary: {} tests run in {:.2}s ({} passed, {} failed, {} skipped)",
             suite_name,
-            test_states_final.len(),
-            suite_duration.as_secs_f32(),
-            passed,
-            failed,
-            skipped
+            result.test_states.len(),
+            result.duration.as_secs_f32(),
+            result.passed(),
+            result.failed(),
+            result.skipped()
         ));
 
-        if settings.report_format != ReportFormat::None {
-            generate_choreo_report(
-                suite_name,
-                suite_start_time.elapsed(),
-                &feature_name,
-                &scenarios_vec,
-                &test_states_final,
-                &test_start_times_final,
-                &mut self.env_vars,
-                &settings,
-                self.verbose,
-            )?;
-
-            if self.verbose {
-                colours::success("Reports generated successfully.");
-            }
-        }
-
-        let failures = test_states_final.values().filter(|s| s.is_failed()).count();
-        if failures > settings.expected_failures {
-            return Err(AppError::TestsFailed {
-                count: failures,
-                expected: settings.expected_failures,
-            });
-        }
-
-        Ok(())
+        Ok(result)
     }
 
     /// Dispatches an action to the correct backend.
     fn execute_action(
         &mut self, // Make it a method
         action: &Action,
-        terminal: &mut TerminalBackend,
-        fs: &FileSystemBackend,
-        web: &mut WebBackend,
+        backends: &mut Backends,
         last_exit_code: &mut Option<i32>,
         timeout_seconds: u64,
     ) {
@@ -269,8 +399,24 @@ impl TestRunner {
 
         let env_vars = &mut self.env_vars;
 
+        // Actions of a named terminal actor go to that actor's own session
+        if let Some(actor) = substituted_action
+            .terminal_actor()
+            .filter(|actor| *actor != "Terminal")
+        {
+            let session = backends.sessions.get_or_start(actor);
+            session.backend.execute_action(
+                &substituted_action,
+                &mut session.last_exit_code,
+                Some(Duration::from_secs(timeout_seconds)),
+                env_vars,
+                self.verbose,
+            );
+            return;
+        }
+
         // Check if it's a terminal action
-        if terminal.execute_action(
+        if backends.terminal.execute_action(
             &substituted_action,
             last_exit_code,
             Some(Duration::from_secs(timeout_seconds)),
@@ -280,12 +426,25 @@ impl TestRunner {
             return;
         }
         // Check if it's a filesystem action
-        if fs.execute_action(&substituted_action, terminal.get_cwd(), env_vars) {
+        if backends
+            .fs
+            .execute_action(&substituted_action, backends.terminal.get_cwd(), env_vars)
+        {
+            return;
+        }
+
+        if backends
+            .mock
+            .execute_action(&substituted_action, self.verbose)
+        {
             return;
         }
 
         // Check if it's a web action
-        if web.execute_action(&substituted_action, env_vars, self.verbose) {
+        if backends
+            .web
+            .execute_action(&substituted_action, env_vars, self.verbose)
+        {
             return;
         } else {
             println!(
@@ -303,6 +462,15 @@ impl TestRunner {
 // --- Thread-safe run_scenario ---
 // Accepts shared Arcs for states/times so multiple scenarios can run in parallel.
 
+/// What the suite's Background leaves to every scenario.
+#[derive(Default)]
+struct BackgroundState {
+    /// HTTP headers set in the background.
+    http_headers: HashMap<String, String>,
+    /// Mock servers started in the background, which keep running for every scenario.
+    mock: MockBackend,
+}
+
 /// Creates a scoped test name: "ScenarioName::TestName"
 /// This ensures test names are unique across scenarios.
 fn scoped_name(scenario_name: &str, test_name: &str) -> String {
@@ -318,19 +486,43 @@ fn run_scenario(
     base_dir: &PathBuf,
     test_states: Arc<Mutex<HashMap<String, TestState>>>,
     test_start_times: Arc<Mutex<HashMap<String, Instant>>>,
-    initial_http_headers: HashMap<String, String>,
+    test_durations: Arc<Mutex<HashMap<String, Duration>>>,
+    test_outputs: Arc<Mutex<HashMap<String, CapturedOutput>>>,
+    background: &BackgroundState,
+    filter: &TestFilter,
+    snapshots: &SnapshotStore,
 ) -> Result<(), AppError> {
-    // Per-scenario isolated backends and mutable state
-    let mut terminal_backend = TerminalBackend::new(base_dir.clone(), settings.clone());
-    let fs_backend = FileSystemBackend::new();
-    let mut web_backend = WebBackend::with_headers(initial_http_headers);
-    let mut system_backend = SystemBackend::new();
+    // A sandboxed scenario runs in its own temp dir instead of the suite's directory.
+    // It is declared before the backends so that they are dropped (and their
+    // processes stopped) before it is deleted.
+    let mut workspace = settings.sandbox.then(Workspace::create).transpose()?;
     let mut variables = env_vars.clone();
+    let mut settings = settings.clone();
+    let cwd = match &workspace {
+        Some(workspace) => {
+            let path = workspace.path().to_string_lossy().to_string();
+            variables.insert("WORKSPACE".to_string(), path.clone());
+            settings.env_overrides.push(("WORKSPACE".to_string(), path));
+            workspace.path().to_path_buf()
+        }
+        None => base_dir.clone(),
+    };
+    let settings = &settings;
+
+    // Per-scenario isolated backends and mutable state
+    let mut backends = Backends {
+        terminal: TerminalBackend::new(cwd.clone(), settings.clone()),
+        sessions: TerminalSessions::new(cwd, settings.clone()),
+        fs: FileSystemBackend::with_snapshots(snapshots.clone()).with_base_dir(base_dir.clone()),
+        web: WebBackend::with_headers(background.http_headers.clone()).with_settings(settings),
+        mock: background.mock.for_scenario(),
+        system: SystemBackend::new(),
+    };
     let test_timeout = Duration::from_secs(settings.timeout_seconds);
     let mut last_exit_code: Option<i32> = None;
     let mut output_buffer = String::new();
 
-    let expanded_tests = expand_foreach_blocks(scenario, &variables);
+    let expanded_tests = expand_foreach_blocks(scenario, &variables)?;
     let mut scenario_clone = scenario.clone();
     scenario_clone.tests = expanded_tests.clone();
 
@@ -344,6 +536,16 @@ fn run_scenario(
         }
     }
 
+    if skip_filtered_tests(scenario, &expanded_tests, filter, &test_states) == 0 {
+        if verbose {
+            colours::info(&format!(
+                "\nSkipping scenario '{}': no tests selected.",
+                scenario.name
+            ));
+        }
+        return Ok(());
+    }
+
     let scenario_start_time = Instant::now();
     colours::info(&format!("\nRunning scenario: '{}'", scenario.name));
     'scenario_loop: loop {
@@ -365,17 +567,7 @@ fn run_scenario(
             locked.clone()
         };
 
-        // Build a scenario-scoped view of states for condition checking (e.g. HasSucceeded).
-        // This maps bare test names to states for tests within the current scenario,
-        // so `Test has_succeeded Setup` resolves to this scenario's `Setup`, not another's.
-        let scenario_prefix = format!("{}::", scenario.name);
-        let scoped_states_for_conditions: HashMap<String, TestState> = states_snapshot
-            .iter()
-            .filter_map(|(k, v)| {
-                k.strip_prefix(&scenario_prefix)
-                    .map(|bare| (bare.to_string(), v.clone()))
-            })
-            .collect();
+        let scoped_states_for_conditions = scoped_states(&states_snapshot, &scenario.name);
 
         // Determine tests to evaluate (not done)
         let tests_to_check: Vec<TestCase> = expanded_tests
@@ -420,7 +612,7 @@ fn run_scenario(
 
                     let sync_test = is_synchronous(test_case);
                     if !sync_test {
-                        terminal_backend.read_pty_output(&mut output_buffer);
+                        backends.terminal.read_pty_output(&mut output_buffer);
                     }
 
                     if check_all_conditions_met(
@@ -428,14 +620,11 @@ fn run_scenario(
                         &given_conditions,
                         &scoped_states_for_conditions,
                         &output_buffer,
-                        &terminal_backend.last_stderr.clone(),
+                        &backends.terminal.last_stderr.clone(),
                         elapsed_since_scenario_start.as_secs_f32(),
                         &mut variables,
                         &last_exit_code,
-                        &fs_backend,
-                        &mut terminal_backend,
-                        &mut web_backend,
-                        &system_backend,
+                        &mut backends,
                         verbose,
                     ) {
                         tests_to_start.push((
@@ -448,13 +637,10 @@ fn run_scenario(
                 }
                 TestState::Running => {
                     if !is_synchronous(test_case) {
-                        terminal_backend.read_pty_output(&mut output_buffer);
+                        backends.terminal.read_pty_output(&mut output_buffer);
+                        record_output(&test_outputs, &scoped, &mut backends);
                     }
 
-                    let elapsed_for_test = start_times_snapshot
-                        .get(&scoped)
-                        .map_or(0.0, |start| start.elapsed().as_secs_f32());
-
                     // Extract conditions from then steps, expanding task calls
                     let mut then_conditions = extract_conditions_from_then_steps(&test_case.then);
                     for step in &test_case.then {
@@ -463,30 +649,45 @@ fn run_scenario(
                             then_conditions.extend(task_conditions);
                         }
                     }
-                    if check_all_conditions_met(
-                        "then",
-                        &then_conditions,
-                        &scoped_states_for_conditions,
+                    let then_check = ThenCheck {
+                        conditions: &then_conditions,
+                        states: &scoped_states_for_conditions,
+                        started: start_times_snapshot.get(&scoped).copied(),
+                        eventually: None,
+                    };
+                    if then_check.run(
                         &output_buffer,
-                        &terminal_backend.last_stderr.clone(),
-                        elapsed_for_test,
                         &mut variables,
-                        &last_exit_code,
-                        &fs_backend,
-                        &mut terminal_backend,
-                        &mut web_backend,
-                        &system_backend,
+                        &mut last_exit_code,
+                        &mut backends,
+                        settings.timeout_seconds,
                         verbose,
                     ) {
+                        backends
+                            .fs
+                            .snapshots
+                            .settle(&snapshot_names(&then_conditions, &variables), true);
                         tests_to_pass.push(test_case.name.clone());
                     } else if start_times_snapshot
                         .get(&scoped)
                         .map_or(false, |start| start.elapsed() > test_timeout)
                     {
-                        immediate_failures.push((
-                            test_case.name.clone(),
-                            format!("Test timed out after {} seconds", settings.timeout_seconds),
-                        ));
+                        let mut error_msg =
+                            format!("Test timed out after {} seconds", settings.timeout_seconds);
+                        let details = describe_condition_failures(
+                            &then_conditions,
+                            &variables,
+                            &output_buffer,
+                            &backends,
+                        );
+                        if !details.is_empty() {
+                            error_msg = format!("{}: {}", error_msg, details.join("; "));
+                        }
+                        backends
+                            .fs
+                            .snapshots
+                            .settle(&snapshot_names(&then_conditions, &variables), false);
+                        immediate_failures.push((test_case.name.clone(), error_msg));
                     }
                 }
                 _ => {}
@@ -517,16 +718,14 @@ fn run_scenario(
                             substitute_variables_in_action(given_action, &mut variables);
                         execute_action(
                             &substituted_action,
-                            &mut terminal_backend,
-                            &fs_backend,
-                            &mut web_backend,
-                            &mut system_backend,
+                            &mut backends,
                             &mut last_exit_code,
                             settings.timeout_seconds,
                             &mut variables,
                             verbose,
                         );
                     }
+                    let when_started = Instant::now();
                     for step in &test_case.when {
                         match step {
                             WhenStep::Action(action) => {
@@ -534,10 +733,7 @@ fn run_scenario(
                                     substitute_variables_in_action(action, &mut variables);
                                 execute_action(
                                     &substituted_action,
-                                    &mut terminal_backend,
-                                    &fs_backend,
-                                    &mut web_backend,
-                                    &mut system_backend,
+                                    &mut backends,
                                     &mut last_exit_code,
                                     settings.timeout_seconds,
                                     &mut variables,
@@ -552,10 +748,7 @@ fn run_scenario(
                                         substitute_variables_in_action(&action, &mut variables);
                                     execute_action(
                                         &substituted_action,
-                                        &mut terminal_backend,
-                                        &fs_backend,
-                                        &mut web_backend,
-                                        &mut system_backend,
+                                        &mut backends,
                                         &mut last_exit_code,
                                         settings.timeout_seconds,
                                         &mut variables,
@@ -566,9 +759,7 @@ fn run_scenario(
                         }
                     }
 
-                    if let Some(137) = last_exit_code {
-                        break;
-                    }
+                    record_output(&test_outputs, &scoped, &mut backends);
 
                     // Extract conditions from then steps, expanding task calls
                     let mut then_conditions_sync =
@@ -579,49 +770,63 @@ fn run_scenario(
                             then_conditions_sync.extend(task_conditions);
                         }
                     }
-                    let passed = check_all_conditions_met(
-                        "then",
-                        &then_conditions_sync,
-                        &{
-                            // Build scoped view for condition checking
-                            let locked = test_states.lock().unwrap();
-                            let prefix = format!("{}::", scenario.name);
-                            locked
-                                .iter()
-                                .filter_map(|(k, v)| {
-                                    k.strip_prefix(&prefix)
-                                        .map(|bare| (bare.to_string(), v.clone()))
-                                })
-                                .collect::<HashMap<String, TestState>>()
-                        },
+                    let states = scoped_states(&test_states.lock().unwrap(), &scenario.name);
+                    let started = test_start_times.lock().unwrap().get(&scoped).copied();
+                    let then_check = ThenCheck {
+                        conditions: &then_conditions_sync,
+                        states: &states,
+                        started,
+                        eventually: eventually_block(test_case).map(|(actions, within, every)| {
+                            (
+                                actions,
+                                when_started + Duration::from_secs_f32(within),
+                                every,
+                            )
+                        }),
+                    };
+                    let passed = then_check.run(
                         &output_buffer,
-                        &terminal_backend.last_stderr.clone(),
-                        test_start_times
-                            .lock()
-                            .unwrap()
-                            .get(&scoped)
-                            .map_or(0.0, |start| start.elapsed().as_secs_f32()),
                         &mut variables,
-                        &last_exit_code,
-                        &fs_backend,
-                        &mut terminal_backend,
-                        &mut web_backend,
-                        &system_backend,
+                        &mut last_exit_code,
+                        &mut backends,
+                        settings.timeout_seconds,
                         verbose,
                     );
-
+                    backends
+                        .fs
+                        .snapshots
+                        .settle(&snapshot_names(&then_conditions_sync, &variables), passed);
+                    // Also keeps output that the conditions read from the sessions
+                    record_output(&test_outputs, &scoped, &mut backends);
+
+                    record_duration(&test_start_times, &test_durations, &scoped);
                     if let Some(mut state_guard) = test_states.lock().ok() {
                         if passed {
                             state_guard.insert(scoped.clone(), TestState::Passed);
                             colours::success(&format!(" 🟢 Test Passed: {}", name));
                         } else {
                             let mut error_msg = "Synchronous test conditions not met".to_string();
-                            if !terminal_backend.last_stderr.is_empty() {
+                            if !backends.terminal.last_stderr.is_empty() {
                                 error_msg = format!(
                                     "Synchronous test failed. Stderr: {}",
-                                    terminal_backend.last_stderr.trim()
+                                    backends.terminal.last_stderr.trim()
                                 );
                             }
+                            if let Some((_, within, _)) = eventually_block(test_case) {
+                                error_msg = format!("Conditions not met within {}s", within);
+                            }
+                            let mut details = describe_condition_failures(
+                                &then_conditions_sync,
+                                &variables,
+                                &output_buffer,
+                                &backends,
+                            );
+                            if polls(test_case) {
+                                details.extend(backends.web.describe_last_response());
+                            }
+                            if !details.is_empty() {
+                                error_msg = format!("{}: {}", error_msg, details.join("; "));
+                            }
                             state_guard
                                 .insert(scoped.clone(), TestState::Failed(error_msg.clone()));
                             colours::error(&format!(" 🔴 Test Failed: {} - {}", name, error_msg));
@@ -657,10 +862,7 @@ fn run_scenario(
                             substitute_variables_in_action(given_action, &mut variables);
                         execute_action(
                             &substituted_action,
-                            &mut terminal_backend,
-                            &fs_backend,
-                            &mut web_backend,
-                            &mut system_backend,
+                            &mut backends,
                             &mut last_exit_code,
                             settings.timeout_seconds,
                             &mut variables,
@@ -674,10 +876,7 @@ fn run_scenario(
                                     substitute_variables_in_action(action, &mut variables);
                                 execute_action(
                                     &substituted_action,
-                                    &mut terminal_backend,
-                                    &fs_backend,
-                                    &mut web_backend,
-                                    &mut system_backend,
+                                    &mut backends,
                                     &mut last_exit_code,
                                     settings.timeout_seconds,
                                     &mut variables,
@@ -692,10 +891,7 @@ fn run_scenario(
                                         substitute_variables_in_action(&action, &mut variables);
                                     execute_action(
                                         &substituted_action,
-                                        &mut terminal_backend,
-                                        &fs_backend,
-                                        &mut web_backend,
-                                        &mut system_backend,
+                                        &mut backends,
                                         &mut last_exit_code,
                                         settings.timeout_seconds,
                                         &mut variables,
@@ -705,6 +901,7 @@ fn run_scenario(
                             }
                         }
                     }
+                    record_output(&test_outputs, &scoped, &mut backends);
                 }
             }
         }
@@ -716,6 +913,7 @@ fn run_scenario(
                 let scoped = scoped_name(&scenario.name, &name);
                 if let Some(state) = states.get_mut(&scoped) {
                     if !state.is_done() {
+                        record_duration(&test_start_times, &test_durations, &scoped);
                         *state = TestState::Passed;
                         colours::success(&format!(" 🟢  Test Passed: {}", name));
                     }
@@ -730,6 +928,7 @@ fn run_scenario(
                 let scoped = scoped_name(&scenario.name, &name);
                 if let Some(state) = states.get_mut(&scoped) {
                     if !state.is_done() {
+                        record_duration(&test_start_times, &test_durations, &scoped);
                         *state = TestState::Failed(error_msg.clone());
                         colours::error(&format!(" 🔴  Test Failed: {} - {}", name, error_msg));
                     }
@@ -756,10 +955,7 @@ fn run_scenario(
                                 substitute_variables_in_action(action, &mut variables);
                             execute_action(
                                 &substituted_action,
-                                &mut terminal_backend,
-                                &fs_backend,
-                                &mut web_backend,
-                                &mut system_backend,
+                                &mut backends,
                                 &mut last_exit_code,
                                 settings.timeout_seconds,
                                 &mut variables,
@@ -773,10 +969,7 @@ fn run_scenario(
                                     substitute_variables_in_action(&action, &mut variables);
                                 execute_action(
                                     &substituted_action,
-                                    &mut terminal_backend,
-                                    &fs_backend,
-                                    &mut web_backend,
-                                    &mut system_backend,
+                                    &mut backends,
                                     &mut last_exit_code,
                                     settings.timeout_seconds,
                                     &mut variables,
@@ -823,16 +1016,183 @@ fn run_scenario(
         }
     } // end scenario loop
 
+    if let Some(workspace) = &mut workspace {
+        let states = test_states.lock().unwrap();
+        let failed = expanded_tests.iter().any(|t| {
+            states
+                .get(&scoped_name(&scenario.name, &t.name))
+                .is_some_and(TestState::is_failed)
+        });
+        if failed {
+            workspace.keep();
+            colours::warn(&format!(
+                "Kept the workspace of failed scenario '{}': {}",
+                scenario.name,
+                workspace.path().display()
+            ));
+        }
+    }
+
     Ok(())
 }
 
+/// A scenario-scoped view of the test states for condition checking (e.g. HasSucceeded).
+/// This maps bare test names to states for tests within the given scenario,
+/// so `Test has_succeeded Setup` resolves to this scenario's `Setup`, not another's.
+fn scoped_states(
+    states: &HashMap<String, TestState>,
+    scenario_name: &str,
+) -> HashMap<String, TestState> {
+    let prefix = format!("{}::", scenario_name);
+    states
+        .iter()
+        .filter_map(|(k, v)| {
+            k.strip_prefix(&prefix)
+                .map(|bare| (bare.to_string(), v.clone()))
+        })
+        .collect()
+}
+
+/// The `then` conditions of a running test and what they are checked with.
+struct ThenCheck<'a> {
+    conditions: &'a [Condition],
+    states: &'a HashMap<String, TestState>,
+    started: Option<Instant>,
+    /// The actions, deadline and interval of the test's `eventually` block.
+    eventually: Option<(&'a [Action], Instant, f32)>,
+}
+
+impl ThenCheck<'_> {
+    /// Checks the conditions. While they fail, the `eventually` block, if any, is repeated
+    /// at its interval until they pass or its time is up.
+    fn run(
+        &self,
+        output_buffer: &str,
+        env_vars: &mut HashMap<String, String>,
+        last_exit_code: &mut Option<i32>,
+        backends: &mut Backends,
+        timeout_seconds: u64,
+        verbose: bool,
+    ) -> bool {
+        loop {
+            let passed = check_all_conditions_met(
+                "then",
+                self.conditions,
+                self.states,
+                output_buffer,
+                &backends.terminal.last_stderr.clone(),
+                self.started
+                    .map_or(0.0, |start| start.elapsed().as_secs_f32()),
+                env_vars,
+                last_exit_code,
+                backends,
+                verbose,
+            );
+            let Some((actions, deadline, every)) = self.eventually else {
+                return passed;
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if passed || remaining.is_zero() {
+                return passed;
+            }
+            thread::sleep(remaining.min(Duration::from_secs_f32(every)));
+            for action in actions {
+                let substituted_action = substitute_variables_in_action(action, env_vars);
+                execute_action(
+                    &substituted_action,
+                    backends,
+                    last_exit_code,
+                    timeout_seconds,
+                    env_vars,
+                    verbose,
+                );
+            }
+        }
+    }
+}
+
+/// The actions, time limit and interval of the test's `eventually` block, if it has one.
+fn eventually_block(test_case: &TestCase) -> Option<(&[Action], f32, f32)> {
+    test_case.when.iter().find_map(|step| match step {
+        WhenStep::Action(Action::Eventually {
+            actions,
+            within,
+            every,
+        }) => Some((actions.as_slice(), *within, *every)),
+        _ => None,
+    })
+}
+
+/// Whether the test repeats requests, with `eventually` or `http_get ... until`.
+fn polls(test_case: &TestCase) -> bool {
+    let given = test_case.given.iter().filter_map(|step| match step {
+        GivenStep::Action(action) => Some(action),
+        _ => None,
+    });
+    let when = test_case.when.iter().filter_map(|step| match step {
+        WhenStep::Action(action) => Some(action),
+        _ => None,
+    });
+    given.chain(when).any(|action| {
+        matches!(
+            action,
+            Action::Eventually { .. } | Action::HttpGet { until: Some(_), .. }
+        )
+    })
+}
+
+/// Marks tests not selected by the filter as skipped. Returns the number of tests left to run.
+fn skip_filtered_tests(
+    scenario: &Scenario,
+    tests: &[TestCase],
+    filter: &TestFilter,
+    test_states: &Mutex<HashMap<String, TestState>>,
+) -> usize {
+    if filter.is_empty() {
+        return tests.len();
+    }
+
+    let selected = filter.select(scenario, tests);
+    let mut states = test_states.lock().unwrap();
+    for test in tests {
+        if !selected.contains(test.name.as_str()) {
+            states.insert(scoped_name(&scenario.name, &test.name), TestState::Skipped);
+        }
+    }
+    selected.len()
+}
+
+/// Stores how long a finished test ran, measured from its recorded start time.
+fn record_duration(
+    test_start_times: &Mutex<HashMap<String, Instant>>,
+    test_durations: &Mutex<HashMap<String, Duration>>,
+    scoped: &str,
+) {
+    if let Some(start) = test_start_times.lock().unwrap().get(scoped) {
+        test_durations
+            .lock()
+            .unwrap()
+            .insert(scoped.to_string(), start.elapsed());
+    }
+}
+
+/// Adds the output of the main terminal and of every named session since the last call to
+/// the test's output, so reports can include it.
+fn record_output(
+    test_outputs: &Mutex<HashMap<String, CapturedOutput>>,
+    scoped: &str,
+    backends: &mut Backends,
+) {
+    let mut outputs = test_outputs.lock().unwrap();
+    let output = outputs.entry(scoped.to_string()).or_default();
+    output.append(backends.terminal.take_output());
+    output.append(backends.sessions.take_output());
+}
+
 /// Dispatches an action to the correct backend.
 fn execute_action(
     action: &Action,
-    terminal: &mut TerminalBackend,
-    fs: &FileSystemBackend,
-    web: &mut WebBackend,
-    system: &mut SystemBackend,
+    backends: &mut Backends,
     last_exit_code: &mut Option<i32>,
     timeout_seconds: u64,
     env_vars: &mut HashMap<String, String>,
@@ -841,16 +1201,49 @@ fn execute_action(
     if verbose {
         colours::info(&format!("[RUNNER] Executing action: {:?}", action));
     }
+    // The runner repeats an `eventually` block while the test's conditions fail
+    if let Action::Eventually { actions, .. } = action {
+        for action in actions {
+            execute_action(
+                action,
+                backends,
+                last_exit_code,
+                timeout_seconds,
+                env_vars,
+                verbose,
+            );
+        }
+        return;
+    }
     // Substitute variables in the action
     let substituted_action = substitute_variables_in_action(action, env_vars);
 
     // Check if it's a system action first
-    if system.execute_action(&substituted_action, env_vars, verbose) {
+    if backends
+        .system
+        .execute_action(&substituted_action, env_vars, verbose)
+    {
+        return;
+    }
+
+    // Actions of a named terminal actor (e.g. `Server run`) go to that actor's own session
+    if let Some(actor) = substituted_action
+        .terminal_actor()
+        .filter(|actor| *actor != "Terminal")
+    {
+        let session = backends.sessions.get_or_start(actor);
+        session.backend.execute_action(
+            &substituted_action,
+            &mut session.last_exit_code,
+            Some(Duration::from_secs(timeout_seconds)),
+            env_vars,
+            verbose,
+        );
         return;
     }
 
     // Check if it's a terminal action
-    if terminal.execute_action(
+    if backends.terminal.execute_action(
         &substituted_action,
         last_exit_code,
         Some(Duration::from_secs(timeout_seconds)),
@@ -860,12 +1253,22 @@ fn execute_action(
         return;
     }
     // Check if it's a filesystem action
-    if fs.execute_action(&substituted_action, terminal.get_cwd(), env_vars) {
+    if backends
+        .fs
+        .execute_action(&substituted_action, backends.terminal.get_cwd(), env_vars)
+    {
+        return;
+    }
+
+    if backends.mock.execute_action(&substituted_action, verbose) {
         return;
     }
 
     // Check if it's a web action
-    if web.execute_action(&substituted_action, env_vars, verbose) {
+    if backends
+        .web
+        .execute_action(&substituted_action, env_vars, verbose)
+    {
         return;
     } else {
         println!(